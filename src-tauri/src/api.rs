@@ -12,4 +12,15 @@ pub trait TauriEvent {
             error!("Error occurred while sending event: {}", e);
         });
     }
+
+    /// Send event to the topic suffixed with a scope, e.g. `app://openocd/output/<scope>`
+    fn send_scoped_to(self, window: &tauri::Window, scope: &str)
+    where
+        Self: Sized + Serialize + Clone,
+    {
+        let topic = format!("{}/{}", self.topic(), scope);
+        window.emit(&topic, self).unwrap_or_else(|e| {
+            error!("Error occurred while sending event: {}", e);
+        });
+    }
 }
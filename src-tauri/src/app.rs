@@ -1,47 +1,58 @@
-use log::{error, info, warn};
-use std::io::{BufRead, BufReader};
-use std::{
-    process::Child,
-    sync::{Arc, Mutex},
-};
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tauri::Window;
-use threadpool::ThreadPool;
 
 use crate::api::TauriEvent;
 use crate::{
     config::AppConfig,
     error::ErrorMsg,
+    gdb::{Gdb, GdbLaunch},
     gitpod,
     notification::Notification,
     openocd,
     openocd::config::{Config, ConfigsSet},
+    openocd::console::Console,
+    openocd::events::TargetState,
+    openocd::flash::SharedTracker,
+    openocd::ports::Ports,
+    openocd::proc::LaunchOptions,
+    openocd::rpc::TclClient,
+    openocd::session::{Session, SessionInfo, SessionWindow, SharedAttached, Shutdown, Started},
+    svd,
 };
 
 pub struct App {
-    pub openocd_workers: ThreadPool,
-    pub openocd_proc: Arc<Mutex<Option<Arc<Mutex<Child>>>>>,
+    pub sessions: HashMap<String, Session>,
     pub gitpod_handlers: Option<gitpod::proc::Handlers>,
+    /// Device description loaded from an SVD file
+    pub svd: Option<svd::Device>,
+    /// Configs classified by the first listing, scanning takes a while
+    configs: Option<ConfigsSet>,
 }
 
 impl App {
     pub fn new() -> Arc<Mutex<Self>> {
         Arc::new(Mutex::new(App {
-            openocd_workers: ThreadPool::new(1),
-            openocd_proc: Arc::new(Mutex::new(None)),
+            sessions: HashMap::new(),
             gitpod_handlers: None,
+            svd: None,
+            configs: None,
         }))
     }
 
     /// Start openocd as process with provided configs as args
     ///
     /// Started process emits event `app://openocd/output` on every received line of
-    /// openocd output to stderr. Return status as a string.
+    /// openocd output to stderr. Return status with ports OpenOCD was started with.
     ///
     /// List of configs can be retrieved with `get_config_list`.
     ///
     /// # Arguments
     ///
     /// * `configs` — an array of `Config` to run OpenOCD with.
+    /// * `options` — launch options, e.g. a serial number of the probe.
     /// * `state` — a tauri object for storing openocd process handler.
     /// * `window` — a tauri windows object to emit events.
     ///
@@ -56,13 +67,63 @@ impl App {
     /// ], ...)
     /// ```
     ///
-    pub fn start(&self, configs: Vec<Config>, window: Window) -> Result<String, ErrorMsg> {
-        if self.openocd_workers.active_count() > 0 {
-            Err("OpenOCD has been already started!".into())
-        } else {
-            self.start_openocd(configs, window);
-            Ok("OpenOCD started!".into())
+    pub fn start(
+        &mut self,
+        configs: Vec<Config>,
+        options: LaunchOptions,
+        window: Window,
+    ) -> Result<Started, ErrorMsg> {
+        self.start_session(
+            openocd::session::DEFAULT_ID.into(),
+            configs,
+            options,
+            window,
+        )
+    }
+
+    /// Start OpenOCD of a named session with provided configs as args
+    ///
+    /// A new session gets the first set of gdb, telnet and tcl ports not used
+    /// by other sessions, busy ports are reassigned out of them too. Events of the session are emitted to topics suffixed
+    /// with its ID, e.g. `app://openocd/output/<id>`. The default session uses
+    /// the plain topics.
+    ///
+    pub fn start_session(
+        &mut self,
+        id: String,
+        configs: Vec<Config>,
+        options: LaunchOptions,
+        window: Window,
+    ) -> Result<Started, ErrorMsg> {
+        Session::validate_id(&id)?;
+
+        if !self.sessions.contains_key(&id) {
+            let ports = self.free_ports();
+            self.sessions
+                .insert(id.clone(), Session::new(id.clone(), ports));
         }
+
+        let reserved: Vec<Ports> = self
+            .sessions
+            .values()
+            .filter(|session| session.id != id)
+            .map(|session| session.ports)
+            .collect();
+
+        self.session_mut(Some(id))?
+            .start(configs, options, &reserved, window)
+    }
+
+    /// Return short descriptions of all known sessions
+    ///
+    pub fn list_sessions(&self) -> Vec<SessionInfo> {
+        let mut sessions: Vec<SessionInfo> = self
+            .sessions
+            .values()
+            .map(|session| session.info())
+            .collect();
+        sessions.sort_by(|a, b| a.id.cmp(&b.id));
+        sessions
     }
 
     pub fn start_gitpod(
@@ -97,9 +158,11 @@ impl App {
 
     /// Dump selected fields with configs in GUI
     ///
+    /// Fields missing in `dumped`, e.g. GDB paths, keep their stored values.
     /// Return string with status.
     ///
     pub fn dump_state(&self, dumped: AppConfig) -> Result<String, ErrorMsg> {
+        let dumped = dumped.with_stored(self.load_state());
         let res = confy::store("justrunmydebugger-config", dumped);
 
         match res {
@@ -121,88 +184,253 @@ impl App {
         }
     }
 
-    /// Kill started OpenOCD process if it was started
+    /// Prepare a shutdown of OpenOCD process of the default session
     ///
-    /// Return error string if something gone wrong.
+    /// Returned `Shutdown` must be run to stop the process. Stop event with
+    /// an exit status is emitted after the process is reaped.
+    /// Return error string if OpenOCD is not started.
     ///
-    pub fn kill(&self, window: &Window) -> Result<String, ErrorMsg> {
-        let res: Option<&str> = self.openocd_proc.lock().unwrap().as_mut().and_then(|proc| {
-            if !openocd::proc::kill_proc(&mut proc.lock().unwrap()) {
-                error!("OpenOCD was not killed!");
-                Some("OpenOCD was not killed!")
-            } else {
-                info!("OpenOCD killed.");
-                None
-            }
-        });
+    pub fn kill(&mut self, timeout: Duration, window: Window) -> Result<Shutdown, ErrorMsg> {
+        self.kill_session(None, timeout, window)
+    }
 
-        match res {
-            Some(err) => Err(err.into()),
-            None => {
-                openocd::events::Event::Stop.send_to(window);
-                Ok("OpenOCD stopped".into())
-            }
-        }
+    /// Prepare a shutdown of OpenOCD process of a session
+    ///
+    /// Session is selected by ID, the default session is used if ID is `None`.
+    ///
+    pub fn kill_session(
+        &mut self,
+        id: Option<String>,
+        timeout: Duration,
+        window: Window,
+    ) -> Result<Shutdown, ErrorMsg> {
+        self.session_mut(id)?.shutdown(timeout, window)
     }
 
-    /// Return a struct with three lists of `Config`
+    /// Send a TCL command to the running OpenOCD and return its response
     ///
-    /// Read cfg files in the script folder of OpenOCD and return them
-    /// as three list of `Config { name, path }`: boards, interfaces and targets.
-    /// Return empty vector if configs was not found.
+    /// Command is sent to the TCL server of OpenOCD of the session.
     ///
-    pub fn get_config_lists(&self) -> Result<ConfigsSet, ErrorMsg> {
-        ConfigsSet::new().map_err(|s| ErrorMsg { message: s })
+    pub fn send_tcl(&self, session: Option<String>, command: String) -> Result<String, ErrorMsg> {
+        let session = self.session(session)?;
+        session.ensure_started()?;
+
+        let mut client = TclClient::connect(session.ports.tcl)?;
+        client.execute(&command)
     }
 
-    fn start_openocd(&self, configs: Vec<Config>, window: Window) {
-        let openocd_proc = self.openocd_proc.clone();
+    /// Load peripherals of the device from an SVD file
+    ///
+    /// Previously loaded SVD file is replaced. Return the device summary
+    /// without registers.
+    ///
+    pub fn load_svd(&mut self, path: String) -> Result<svd::DeviceSummary, ErrorMsg> {
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Can't read SVD file \"{}\": {}", path, e))?;
+        let device = svd::Device::parse(&content)?;
+        let summary = device.summary();
+        self.svd.replace(device);
 
-        self.openocd_workers.execute(move || {
-            let command = openocd::proc::start(&configs);
+        Ok(summary)
+    }
 
-            if let Some(command) = command {
-                let cmd = Arc::new(Mutex::new(command));
-                openocd_proc.lock().unwrap().replace(cmd.clone());
+    /// Return a peripheral of the loaded SVD file
+    ///
+    /// The peripheral is copied, so its registers are read without holding
+    /// the `App` lock.
+    ///
+    pub fn svd_peripheral(&self, name: &str) -> Result<svd::Peripheral, ErrorMsg> {
+        let device = self
+            .svd
+            .as_ref()
+            .ok_or_else(|| ErrorMsg::from("SVD file is not loaded!"))?;
+        device
+            .peripheral(name)
+            .cloned()
+            .ok_or_else(|| format!("Peripheral {} not found!", name).into())
+    }
 
-                let stderr = cmd.lock().unwrap().stderr.take().unwrap();
-                let reader = BufReader::new(stderr);
+    /// Return the TCL port of the running OpenOCD of a session
+    ///
+    /// Long running commands, e.g. programming, are sent to the port without
+    /// holding the `App` lock.
+    ///
+    pub fn tcl_port(&self, session: Option<String>) -> Result<u16, ErrorMsg> {
+        let session = self.session(session)?;
+        session.ensure_started()?;
+        Ok(session.ports.tcl)
+    }
 
-                App::send_event(&window, openocd::events::Event::Start, None);
+    /// Return connections and processes attached to OpenOCD of a session
+    ///
+    /// RTT and SWO talk to OpenOCD while they are started and stopped, so
+    /// they are attached without holding the `App` lock.
+    ///
+    pub fn attached(&self, session: Option<String>) -> Result<SharedAttached, ErrorMsg> {
+        Ok(self.session(session)?.shared_attached())
+    }
 
-                reader
-                    .lines()
-                    .filter_map(|line| line.ok())
-                    .for_each(|line| {
-                        format!("{}\n", line).send_to(&window);
-                        info!("-- {}", line);
-                    });
+    /// Return the tracker of programming progress of a running session
+    ///
+    pub fn flash_progress(&self, session: Option<String>) -> Result<SharedTracker, ErrorMsg> {
+        let session = self.session(session)?;
+        session.ensure_started()?;
+        Ok(session.flash_progress())
+    }
 
-                App::send_event(&window, openocd::events::Event::Stop, None)
-            } else {
-                App::send_event(
-                    &window,
-                    openocd::events::Event::Stop,
-                    Some("OpenOCD was not started!"),
-                )
-            }
-        });
+    /// Connect a console session to the telnet server of the running OpenOCD
+    ///
+    /// Console output is emitted as `app://openocd/console` event of the
+    /// session. Previously opened console of the session is closed.
+    ///
+    pub fn open_console(
+        &mut self,
+        session: Option<String>,
+        window: Window,
+    ) -> Result<String, ErrorMsg> {
+        let session = self.session_mut(session)?;
+        session.ensure_started()?;
+
+        session.attached().console.take();
+        let window = SessionWindow {
+            window,
+            id: session.id.clone(),
+        };
+        let console = Console::open(session.ports.telnet, window)?;
+        session.attached().console.replace(console);
+
+        Ok("Console opened!".into())
     }
 
-    fn send_event(window: &Window, event: openocd::events::Event, msg: Option<&str>) {
-        type Event = openocd::events::Event;
-        match event {
-            Event::Start => {
-                let msg = msg.map_or("OpenOCD started!", |s| s);
-                info!("{}", msg);
-                Notification::info(msg.into()).send_to(window);
-            }
-            Event::Stop => {
-                let msg = msg.map_or("OpenOCD stopped!", |s| s);
-                warn!("{}", msg);
-                Notification::warn(msg.into()).send_to(window);
-            }
+    /// Send a line of input to the opened console session
+    ///
+    pub fn console_send(
+        &mut self,
+        session: Option<String>,
+        line: String,
+    ) -> Result<String, ErrorMsg> {
+        self.session_mut(session)?
+            .attached()
+            .console
+            .as_mut()
+            .ok_or_else(|| ErrorMsg::from("Console is not opened!"))
+            .and_then(|console| console.send(line))
+            .map(|_| "Sent!".into())
+    }
+
+    /// Return lines sent in the opened console session, oldest first
+    ///
+    pub fn console_history(&self, session: Option<String>) -> Result<Vec<String>, ErrorMsg> {
+        self.session(session)?
+            .attached()
+            .console
+            .as_ref()
+            .map(|console| console.history())
+            .ok_or_else(|| "Console is not opened!".into())
+    }
+
+    /// Close the opened console session
+    ///
+    pub fn close_console(&mut self, session: Option<String>) -> Result<String, ErrorMsg> {
+        self.session_mut(session)?
+            .attached()
+            .console
+            .take()
+            .map(|_| "Console closed!".into())
+            .ok_or_else(|| "Console is not opened!".into())
+    }
+
+    /// Start GDB attached to the gdb server of the session
+    ///
+    /// GDB is searched in the paths of the app config if its path is not
+    /// provided. GDB is killed when the session is stopped. Previously
+    /// launched GDB of the session is killed.
+    ///
+    pub fn launch_gdb(
+        &mut self,
+        session: Option<String>,
+        launch: GdbLaunch,
+        window: Window,
+    ) -> Result<String, ErrorMsg> {
+        let paths = self.load_state().gdb_paths().to_vec();
+        let session = self.session_mut(session)?;
+        session.ensure_started()?;
+
+        session.attached().gdb.take();
+        let window = SessionWindow {
+            window,
+            id: session.id.clone(),
+        };
+        let gdb = Gdb::launch(session.ports.gdb, &launch, &paths, window)?;
+        session.attached().gdb.replace(gdb);
+
+        Ok("GDB launched!".into())
+    }
+
+    /// Kill GDB of the session
+    ///
+    pub fn stop_gdb(&mut self, session: Option<String>) -> Result<String, ErrorMsg> {
+        self.session_mut(session)?
+            .attached()
+            .gdb
+            .take()
+            .map(|_| "GDB stopped!".into())
+            .ok_or_else(|| "GDB is not launched!".into())
+    }
+
+    /// Return run states of targets of the session
+    ///
+    /// States are read from OpenOCD if they are not tracked yet.
+    ///
+    pub fn target_states(&self, session: Option<String>) -> Result<Vec<TargetState>, ErrorMsg> {
+        let session = self.session(session)?;
+        session.ensure_started()?;
+
+        let states = session.target_states();
+        if !states.is_empty() {
+            return Ok(states);
         }
-        event.send_to(window);
+
+        let mut tcl = TclClient::connect(session.ports.tcl)?;
+        openocd::target::read_states(&mut tcl)
+    }
+
+    /// Return configs classified by an earlier listing
+    pub fn cached_configs(&self) -> Option<ConfigsSet> {
+        self.configs.clone()
+    }
+
+    /// Keep classified configs for next listings
+    ///
+    /// Configs are scanned without the lock, so the result of a concurrent
+    /// listing may replace the cached one, they are the same.
+    pub fn cache_configs(&mut self, configs: ConfigsSet) {
+        self.configs = Some(configs);
+    }
+
+    fn session(&self, id: Option<String>) -> Result<&Session, ErrorMsg> {
+        let id = id.unwrap_or_else(|| openocd::session::DEFAULT_ID.into());
+        self.sessions
+            .get(&id)
+            .ok_or_else(|| format!(r#"Session "{}" not found!"#, id).into())
+    }
+
+    fn session_mut(&mut self, id: Option<String>) -> Result<&mut Session, ErrorMsg> {
+        let id = id.unwrap_or_else(|| openocd::session::DEFAULT_ID.into());
+        self.sessions
+            .get_mut(&id)
+            .ok_or_else(|| format!(r#"Session "{}" not found!"#, id).into())
+    }
+
+    /// Return the first set of ports not used by any session
+    fn free_ports(&self) -> Ports {
+        (0..)
+            .map(Ports::nth)
+            .find(|ports| {
+                self.sessions
+                    .values()
+                    .all(|session| !session.ports.overlaps(ports))
+            })
+            .unwrap_or_default()
     }
 }
@@ -1,29 +1,779 @@
 use log::info;
+use std::path::Path;
+use std::time::Duration;
 use tauri::Window;
 
 use crate::config::AppConfig;
 use crate::error::ErrorMsg;
-use crate::openocd::config::{Config, ConfigsSet};
+use crate::gdb::{self, GdbInfo, GdbLaunch};
+use crate::openocd;
+use crate::openocd::banks::{BankInfo, FlashBank, FlashOperation, Outcome};
+use crate::openocd::board::Implied;
+use crate::openocd::config::{Config, ConfigFilter, ConfigsSet};
+use crate::openocd::control::{ControlResult, ResetMode};
+use crate::openocd::events::TargetState;
+use crate::openocd::flash::{ProgramRequest, ProgramResult};
+use crate::openocd::memory::{MemoryBlock, Width};
+use crate::openocd::probe::Probe;
+use crate::openocd::proc::LaunchOptions;
+use crate::openocd::rpc::TclClient;
+use crate::openocd::rtt::{Channels, Rtt, RttSetup};
+use crate::openocd::script::ConfigScan;
+use crate::openocd::session::{SessionInfo, SessionWindow, Started};
+use crate::openocd::swo::{Swo, SwoSetup};
+use crate::openocd::version::Capabilities;
 use crate::state::State;
+use crate::svd::decode::DecodedPeripheral;
+use crate::svd::{self, DeviceSummary};
+
+/// Timeout of a single OpenOCD shutdown step if it is not provided
+const SHUTDOWN_TIMEOUT_MS: u64 = 3000;
+
+fn shutdown_timeout(timeout_ms: Option<u64>) -> Duration {
+    Duration::from_millis(timeout_ms.unwrap_or(SHUTDOWN_TIMEOUT_MS))
+}
 
 /// Return a struct with three lists of `Config`
 ///
 /// Read cfg files in the script folder of OpenOCD and return them
 /// as three list of `Config { name, path }`: boards, interfaces and targets.
+/// Configs are classified by the adapter driver, CPU architectures and vendor.
+/// Classification scans every cfg file, so it is done once and cached.
 /// Return empty vector if configs was not found.
 ///
-#[tauri::command]
+#[tauri::command(async)]
 pub fn get_config_lists(state: tauri::State<State>) -> Result<ConfigsSet, ErrorMsg> {
-    state.app.lock().unwrap().get_config_lists()
+    configs_set(&state)
+}
+
+/// Return debug probes plugged in via USB with matching interface configs
+///
+/// Probes are discovered through sysfs on Linux only.
+///
+#[tauri::command(async)]
+pub fn list_probes(state: tauri::State<State>) -> Vec<Probe> {
+    let configs = configs_set(&state);
+    let interfaces = configs
+        .as_ref()
+        .map_or(&[][..], |configs| configs.interfaces());
+
+    openocd::probe::discover(Path::new(openocd::probe::SYSFS_USB_DEVICES), interfaces)
+}
+
+/// Return cached configs or scan them without holding the app lock
+fn configs_set(state: &State) -> Result<ConfigsSet, ErrorMsg> {
+    if let Some(configs) = state.app.lock().unwrap().cached_configs() {
+        return Ok(configs);
+    }
+
+    let configs = ConfigsSet::new().map_err(|s| ErrorMsg { message: s })?;
+    state.app.lock().unwrap().cache_configs(configs.clone());
+    Ok(configs)
+}
+
+/// Stop started OpenOCD process if it was started
+///
+/// OpenOCD is asked to shut down over the TCL connection first, then
+/// SIGTERM is sent to it and finally it is killed. Every next step is taken
+/// after `timeout_ms` (3 seconds by default) and reported as
+/// `app://openocd/shutdown` event.
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn kill(
+    timeout_ms: Option<u64>,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<String, ErrorMsg> {
+    let timeout = shutdown_timeout(timeout_ms);
+    let shutdown = state.app.lock().unwrap().kill(timeout, window)?;
+    shutdown.run()
 }
 
-/// Kill started OpenOCD process if it was started
+/// Start OpenOCD of a named session with provided configs as args
 ///
+/// Every session has its own process, ports and event topics suffixed with
+/// the session ID, e.g. `app://openocd/output/<id>`.
 /// Return error string if something gone wrong.
 ///
 #[tauri::command]
-pub fn kill(state: tauri::State<State>, window: Window) -> Result<String, ErrorMsg> {
-    state.app.lock().unwrap().kill(&window)
+pub fn start_session(
+    id: String,
+    configs: Vec<Config>,
+    options: Option<LaunchOptions>,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<Started, ErrorMsg> {
+    info!(
+        r#"Start openocd session "{}" with configs: "{:?}""#,
+        id,
+        configs
+            .iter()
+            .map(|config| config.path.as_str())
+            .collect::<Vec<&str>>()
+    );
+
+    state
+        .app
+        .lock()
+        .unwrap()
+        .start_session(id, configs, options.unwrap_or_default(), window)
+}
+
+/// Stop started OpenOCD process of a session
+///
+/// Shutdown is done the same way as by `kill`.
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn kill_session(
+    id: String,
+    timeout_ms: Option<u64>,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<String, ErrorMsg> {
+    let timeout = shutdown_timeout(timeout_ms);
+    let shutdown = state
+        .app
+        .lock()
+        .unwrap()
+        .kill_session(Some(id), timeout, window)?;
+    shutdown.run()
+}
+
+/// Return list of known OpenOCD sessions with their configs and ports
+///
+#[tauri::command]
+pub fn list_sessions(state: tauri::State<State>) -> Vec<SessionInfo> {
+    state.app.lock().unwrap().list_sessions()
+}
+
+/// Send a TCL command to the running OpenOCD
+///
+/// Command is sent to the default session if `session` is not provided.
+/// Return the response of OpenOCD or error string if something gone wrong.
+///
+#[tauri::command]
+pub fn send_tcl(
+    session: Option<String>,
+    command: String,
+    state: tauri::State<State>,
+) -> Result<String, ErrorMsg> {
+    info!("Send TCL command: {}", command);
+    state.app.lock().unwrap().send_tcl(session, command)
+}
+
+/// Program a firmware image with OpenOCD `program` command
+///
+/// If `configs` are provided, a separate OpenOCD is started with them and
+/// exits after programming. Otherwise the image is programmed with the
+/// running OpenOCD of the session (the default one if `session` is not
+/// provided), which is shut down afterwards if `exit` is requested.
+/// Programming phases are emitted as `app://openocd/flash` event. OpenOCD
+/// reports nothing within a phase, so the percentage of the event jumps when
+/// the erase and write or the verification finishes; the frontend shows the
+/// phase along with it.
+/// Return structured result of programming or error string if the request
+/// is bad.
+///
+#[tauri::command(async)]
+pub fn program(
+    request: ProgramRequest,
+    configs: Option<Vec<Config>>,
+    options: Option<LaunchOptions>,
+    session: Option<String>,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<ProgramResult, ErrorMsg> {
+    info!("Program image: {}", request.path);
+
+    let events = SessionWindow {
+        window: window.clone(),
+        id: session
+            .clone()
+            .unwrap_or_else(|| openocd::session::DEFAULT_ID.into()),
+    };
+
+    if let Some(configs) = configs {
+        let options = options.unwrap_or_default();
+        return openocd::flash::program_once(&configs, &options, &request, &events);
+    }
+
+    let (tcl_port, tracker) = {
+        let app = state.app.lock().unwrap();
+        (
+            app.tcl_port(session.clone())?,
+            app.flash_progress(session.clone())?,
+        )
+    };
+    let result = openocd::flash::program_in_session(tcl_port, &tracker, &request, &events)?;
+
+    if result.success && request.exit {
+        let timeout = shutdown_timeout(None);
+        let shutdown = state
+            .app
+            .lock()
+            .unwrap()
+            .kill_session(session, timeout, window)?;
+        shutdown.run()?;
+    }
+
+    Ok(result)
+}
+
+/// Halt the current target of the running OpenOCD
+///
+/// The default session is used if `session` is not provided.
+/// Return run states of targets after the halt or error string if no
+/// session is running or the target was not halted.
+///
+#[tauri::command(async)]
+pub fn halt(
+    session: Option<String>,
+    state: tauri::State<State>,
+) -> Result<ControlResult, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    let mut client = TclClient::connect(tcl_port)?;
+    openocd::control::halt(&mut client)
+}
+
+/// Resume the current target of the running OpenOCD
+///
+/// The target continues at `address` if provided, otherwise at the current
+/// PC.
+/// Return run states of targets or error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn resume(
+    session: Option<String>,
+    address: Option<u64>,
+    state: tauri::State<State>,
+) -> Result<ControlResult, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    let mut client = TclClient::connect(tcl_port)?;
+    openocd::control::resume(&mut client, address)
+}
+
+/// Execute a single instruction of the halted current target
+///
+/// The instruction at `address` is executed if provided, otherwise the one
+/// at the current PC.
+/// Return run states of targets or error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn step(
+    session: Option<String>,
+    address: Option<u64>,
+    state: tauri::State<State>,
+) -> Result<ControlResult, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    let mut client = TclClient::connect(tcl_port)?;
+    openocd::control::step(&mut client, address)
+}
+
+/// Reset targets of the running OpenOCD
+///
+/// `mode` is `run`, `halt` or `init`, the same as of `reset` command.
+/// Return run states of targets or error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn reset(
+    session: Option<String>,
+    mode: ResetMode,
+    state: tauri::State<State>,
+) -> Result<ControlResult, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    let mut client = TclClient::connect(tcl_port)?;
+    openocd::control::reset(&mut client, mode)
+}
+
+/// List flash banks of targets of the running OpenOCD
+///
+/// Return banks declared by the target configs or error string if
+/// something gone wrong.
+///
+#[tauri::command(async)]
+pub fn list_flash_banks(
+    session: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Vec<FlashBank>, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    openocd::banks::list(&mut TclClient::connect(tcl_port)?)
+}
+
+/// Probe a flash bank of the running OpenOCD and return its sectors
+///
+/// Return sectors with their protection or error string if something gone
+/// wrong.
+///
+#[tauri::command(async)]
+pub fn flash_info(
+    session: Option<String>,
+    bank: u32,
+    state: tauri::State<State>,
+) -> Result<BankInfo, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    openocd::banks::info(&mut TclClient::connect(tcl_port)?, bank)
+}
+
+/// Run a flash operation with the running OpenOCD after a confirmation
+///
+/// Without `confirmed` nothing is changed, the returned outcome carries a
+/// message to confirm and the planned command. The operation is run on the
+/// second call with `confirmed` set to that command.
+fn flash_operation(
+    session: Option<String>,
+    operation: FlashOperation,
+    confirmed: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Outcome, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    openocd::banks::run(
+        &mut TclClient::connect(tcl_port)?,
+        &operation,
+        confirmed.as_deref(),
+    )
+}
+
+/// Erase a whole flash bank
+///
+/// Driver specific `mass_erase` is used if available. Return a message to
+/// confirm unless `confirmed` is the command planned by the previous call,
+/// then the result of the erase.
+///
+#[tauri::command(async)]
+pub fn mass_erase(
+    session: Option<String>,
+    bank: u32,
+    confirmed: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Outcome, ErrorMsg> {
+    flash_operation(
+        session,
+        FlashOperation::MassErase { bank },
+        confirmed,
+        state,
+    )
+}
+
+/// Erase sectors `first..=last` of a flash bank
+///
+/// Return a message to confirm unless `confirmed` is the command planned
+/// by the previous call, then the result of the erase.
+///
+#[tauri::command(async)]
+pub fn erase_sectors(
+    session: Option<String>,
+    bank: u32,
+    first: u32,
+    last: u32,
+    confirmed: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Outcome, ErrorMsg> {
+    flash_operation(
+        session,
+        FlashOperation::EraseSectors { bank, first, last },
+        confirmed,
+        state,
+    )
+}
+
+/// Enable or disable write protection of sectors `first..=last`
+///
+/// Return a message to confirm unless `confirmed` is the command planned
+/// by the previous call, then the result of the change.
+///
+#[tauri::command(async)]
+pub fn set_write_protection(
+    session: Option<String>,
+    bank: u32,
+    first: u32,
+    last: u32,
+    enable: bool,
+    confirmed: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Outcome, ErrorMsg> {
+    let operation = FlashOperation::WriteProtect {
+        bank,
+        first,
+        last,
+        enable,
+    };
+    flash_operation(session, operation, confirmed, state)
+}
+
+/// Lock or unlock the chip against reads, e.g. `stm32f2x unlock 0`
+///
+/// Supported by STM32 flash drivers only. Unlock mass erases the chip.
+/// Return a message to confirm unless `confirmed` is the command planned
+/// by the previous call, then the result of the change.
+///
+#[tauri::command(async)]
+pub fn set_read_protection(
+    session: Option<String>,
+    bank: u32,
+    enable: bool,
+    confirmed: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Outcome, ErrorMsg> {
+    flash_operation(
+        session,
+        FlashOperation::ReadProtect { bank, enable },
+        confirmed,
+        state,
+    )
+}
+
+/// Read values from the target memory of the running OpenOCD
+///
+/// `width` is 8, 16, 32 or 64 bits, byte ranges are read with 8-bit width.
+/// The default session is used if `session` is not provided.
+/// Return values with the address of the first one or error string if
+/// something gone wrong.
+///
+#[tauri::command(async)]
+pub fn read_memory(
+    session: Option<String>,
+    address: u64,
+    width: Width,
+    count: u64,
+    state: tauri::State<State>,
+) -> Result<MemoryBlock, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    let mut client = TclClient::connect(tcl_port)?;
+    openocd::memory::read(&mut client, address, width, count)
+}
+
+/// Write values to the target memory of the running OpenOCD
+///
+/// Values are written to consecutive addresses starting at `address`.
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn write_memory(
+    session: Option<String>,
+    address: u64,
+    width: Width,
+    values: Vec<u64>,
+    state: tauri::State<State>,
+) -> Result<String, ErrorMsg> {
+    let tcl_port = state.app.lock().unwrap().tcl_port(session)?;
+    let mut client = TclClient::connect(tcl_port)?;
+    openocd::memory::write(&mut client, address, width, &values)
+        .map(|_| format!("{} values written!", values.len()))
+}
+
+/// Load peripherals, registers and bitfields from a CMSIS SVD file
+///
+/// Return the device with the list of its peripherals or error string if
+/// the file can't be parsed.
+///
+#[tauri::command]
+pub fn load_svd(path: String, state: tauri::State<State>) -> Result<DeviceSummary, ErrorMsg> {
+    info!("Load SVD file: {}", path);
+    state.app.lock().unwrap().load_svd(path)
+}
+
+/// Read registers of a peripheral from the running OpenOCD and decode them
+///
+/// Peripheral is looked up in the loaded SVD file. A register which can't
+/// be read is returned with an error instead of a value.
+///
+#[tauri::command(async)]
+pub fn read_peripheral(
+    session: Option<String>,
+    peripheral: String,
+    state: tauri::State<State>,
+) -> Result<DecodedPeripheral, ErrorMsg> {
+    let (peripheral, tcl_port) = {
+        let app = state.app.lock().unwrap();
+        (app.svd_peripheral(&peripheral)?, app.tcl_port(session)?)
+    };
+    let mut client = TclClient::connect(tcl_port)?;
+    Ok(svd::decode::read_peripheral(&mut client, &peripheral))
+}
+
+/// Open console session to the telnet server of the running OpenOCD
+///
+/// Console output is emitted as `app://openocd/console` event.
+/// Return error string if something gone wrong.
+///
+#[tauri::command]
+pub fn open_console(
+    session: Option<String>,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<String, ErrorMsg> {
+    state.app.lock().unwrap().open_console(session, window)
+}
+
+/// Send a line of input to the opened console session
+///
+/// Return error string if something gone wrong.
+///
+#[tauri::command]
+pub fn console_send(
+    session: Option<String>,
+    line: String,
+    state: tauri::State<State>,
+) -> Result<String, ErrorMsg> {
+    state.app.lock().unwrap().console_send(session, line)
+}
+
+/// Return lines sent in the opened console session, oldest first
+///
+#[tauri::command]
+pub fn console_history(
+    session: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Vec<String>, ErrorMsg> {
+    state.app.lock().unwrap().console_history(session)
+}
+
+/// Close the opened console session
+///
+/// Return error string if something gone wrong.
+///
+#[tauri::command]
+pub fn close_console(
+    session: Option<String>,
+    state: tauri::State<State>,
+) -> Result<String, ErrorMsg> {
+    state.app.lock().unwrap().close_console(session)
+}
+
+/// Start RTT of the running OpenOCD and stream its up-channels
+///
+/// The control block is searched in the memory range of `setup`. Output of
+/// up-channels is emitted as `app://openocd/rtt` event. If the firmware ELF
+/// is provided, `defmt` channels are also decoded to log records emitted as
+/// `app://openocd/defmt` event. RTT is stopped together with the OpenOCD
+/// session.
+/// Return found channels or error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn start_rtt(
+    session: Option<String>,
+    setup: RttSetup,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<Channels, ErrorMsg> {
+    let events = SessionWindow {
+        window,
+        id: session
+            .clone()
+            .unwrap_or_else(|| openocd::session::DEFAULT_ID.into()),
+    };
+    let (tcl_port, attached) = {
+        let app = state.app.lock().unwrap();
+        (app.tcl_port(session.clone())?, app.attached(session)?)
+    };
+
+    // RTT of the session is stopped before the next one is set up
+    let previous = attached.lock().unwrap().rtt.take();
+    drop(previous);
+
+    let rtt = Rtt::start(tcl_port, &setup, events)?;
+    let channels = rtt.channels().clone();
+    attached.lock().unwrap().rtt.replace(rtt);
+
+    Ok(channels)
+}
+
+/// Write text to an RTT down-channel
+///
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn rtt_send(
+    session: Option<String>,
+    channel: u32,
+    data: String,
+    state: tauri::State<State>,
+) -> Result<String, ErrorMsg> {
+    let attached = state.app.lock().unwrap().attached(session)?;
+    let mut attached = attached.lock().unwrap();
+
+    attached
+        .rtt
+        .as_mut()
+        .ok_or_else(|| ErrorMsg::from("RTT is not started!"))
+        .and_then(|rtt| rtt.send(channel, data.as_bytes()))
+        .map(|_| "Sent!".into())
+}
+
+/// Stop RTT of the running OpenOCD
+///
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn stop_rtt(session: Option<String>, state: tauri::State<State>) -> Result<String, ErrorMsg> {
+    let attached = state.app.lock().unwrap().attached(session)?;
+    let rtt = attached.lock().unwrap().rtt.take();
+
+    // RTT servers are stopped by drop
+    rtt.map(|_| "RTT stopped!".into())
+        .ok_or_else(|| "RTT is not started!".into())
+}
+
+/// Configure TPIU/SWO of the running OpenOCD and decode the ITM trace
+///
+/// Text written by the target to every ITM stimulus port is emitted as
+/// `app://openocd/swo` event. SWO is stopped together with the OpenOCD
+/// session.
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn start_swo(
+    session: Option<String>,
+    setup: SwoSetup,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<String, ErrorMsg> {
+    let events = SessionWindow {
+        window,
+        id: session
+            .clone()
+            .unwrap_or_else(|| openocd::session::DEFAULT_ID.into()),
+    };
+    let (tcl_port, attached) = {
+        let app = state.app.lock().unwrap();
+        (app.tcl_port(session.clone())?, app.attached(session)?)
+    };
+
+    // SWO of the session is stopped before the next one is configured
+    let previous = attached.lock().unwrap().swo.take();
+    drop(previous);
+
+    let swo = Swo::start(tcl_port, &setup, events)?;
+    attached.lock().unwrap().swo.replace(swo);
+
+    Ok("SWO started!".into())
+}
+
+/// Stop SWO trace of the running OpenOCD
+///
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn stop_swo(session: Option<String>, state: tauri::State<State>) -> Result<String, ErrorMsg> {
+    let attached = state.app.lock().unwrap().attached(session)?;
+    let swo = attached.lock().unwrap().swo.take();
+
+    // TPIU is disabled by drop
+    swo.map(|_| "SWO stopped!".into())
+        .ok_or_else(|| "SWO is not started!".into())
+}
+
+/// Find GDB executables on the host
+///
+/// `paths` to GDB go first, the paths of the app config are used if they
+/// are not provided. Known GDB builds (`arm-none-eabi-gdb`, `gdb-multiarch`,
+/// `riscv*-gdb`) are searched in `PATH`.
+///
+#[tauri::command(async)]
+pub fn find_gdb(paths: Option<Vec<String>>, state: tauri::State<State>) -> Vec<GdbInfo> {
+    let paths = paths.unwrap_or_else(|| {
+        let config = state.app.lock().unwrap().load_state();
+        config.gdb_paths().to_vec()
+    });
+    gdb::find(&paths)
+}
+
+/// Launch GDB attached to the gdb server of the running OpenOCD
+///
+/// GDB is run in a terminal window or headless with its output emitted as
+/// `app://gdb/output` event. GDB is killed together with the OpenOCD
+/// session.
+/// Return error string if something gone wrong.
+///
+#[tauri::command]
+pub fn launch_gdb(
+    session: Option<String>,
+    launch: GdbLaunch,
+    state: tauri::State<State>,
+    window: Window,
+) -> Result<String, ErrorMsg> {
+    state
+        .app
+        .lock()
+        .unwrap()
+        .launch_gdb(session, launch, window)
+}
+
+/// Kill GDB launched for the running OpenOCD
+///
+/// Return error string if something gone wrong.
+///
+#[tauri::command]
+pub fn stop_gdb(session: Option<String>, state: tauri::State<State>) -> Result<String, ErrorMsg> {
+    state.app.lock().unwrap().stop_gdb(session)
+}
+
+/// Return run states of targets of the running OpenOCD
+///
+/// Every state change is also emitted as `app://openocd/target` event, with
+/// PC of halted targets.
+/// Return error string if something gone wrong.
+///
+#[tauri::command(async)]
+pub fn get_target_state(
+    session: Option<String>,
+    state: tauri::State<State>,
+) -> Result<Vec<TargetState>, ErrorMsg> {
+    state.app.lock().unwrap().target_states(session)
+}
+
+/// Return the version of the installed OpenOCD and its supported commands
+///
+/// OpenOCD is run once on the first call, later calls return the same
+/// result. Launch arguments are built according to these capabilities.
+///
+#[tauri::command(async)]
+pub fn get_capabilities() -> Capabilities {
+    Capabilities::get().clone()
+}
+
+/// Return files included by a config with the hardware they describe
+///
+/// `source [find ...]` includes are followed in the script folder of
+/// OpenOCD. Adapter driver, transports, chip name, targets and flash banks
+/// are extracted from every file.
+/// Return error string if the config can't be read.
+///
+#[tauri::command(async)]
+pub fn get_config_tree(config: Config) -> Result<ConfigScan, ErrorMsg> {
+    openocd::script::scan(&config)
+}
+
+/// Return interface and target configs sourced by a board config
+///
+/// Boards usually select the probe and the chip themselves, so these
+/// configs don't need to be passed to OpenOCD again. Interface and target
+/// configs of `selected` which repeat or contradict the ones of the board
+/// are returned as warnings, to be shown before the start.
+/// Return error string if the board config can't be read.
+///
+#[tauri::command(async)]
+pub fn get_board_implied(
+    board: Config,
+    selected: Option<Vec<Config>>,
+) -> Result<Implied, ErrorMsg> {
+    openocd::board::implied(&board, &selected.unwrap_or_default())
+}
+
+/// Return lists of `Config` narrowed by the filter
+///
+/// The driver filters interfaces, the architecture and the vendor filter
+/// boards and targets. Configs which were not classified don't match these
+/// criteria. Return empty lists if configs was not found.
+///
+#[tauri::command(async)]
+pub fn filter_configs(
+    filter: ConfigFilter,
+    state: tauri::State<State>,
+) -> Result<ConfigsSet, ErrorMsg> {
+    configs_set(&state).map(|configs| configs.filter(&filter))
 }
 
 /// Start Gitpod companion
@@ -43,13 +793,16 @@ pub fn start_gitpod(
 /// Start openocd as process with provided configs as args
 ///
 /// Started process emits event `app://openocd/output` on every received line of
-/// openocd output to stderr. Return status as a string.
+/// openocd output to stderr. If `semihosting` option is set, output of the
+/// target is emitted separately as `app://openocd/semihosting` event.
+/// Return status with ports OpenOCD was started with.
 ///
 /// List of configs can be retrieved with `get_config_list`.
 ///
 /// # Arguments
 ///
 /// * `configs` — an array of `Config` to run OpenOCD with.
+/// * `options` — optional launch options, e.g. a serial number of the probe.
 /// * `state` — a tauri object for storing openocd process handler.
 /// * `window` — a tauri windows object to emit events.
 ///
@@ -67,9 +820,10 @@ pub fn start_gitpod(
 #[tauri::command]
 pub fn start(
     configs: Vec<Config>,
+    options: Option<LaunchOptions>,
     state: tauri::State<State>,
     window: Window,
-) -> Result<String, ErrorMsg> {
+) -> Result<Started, ErrorMsg> {
     info!(
         r#"Start openocd with configs: "{:?}""#,
         configs
@@ -79,7 +833,11 @@ pub fn start(
             .collect::<Vec<String>>()
     );
 
-    state.app.lock().unwrap().start(configs, window)
+    state
+        .app
+        .lock()
+        .unwrap()
+        .start(configs, options.unwrap_or_default(), window)
 }
 
 /// Dump selected fields with configs in GUI
@@ -8,10 +8,87 @@ pub struct OpenocdConfigSet {
     board: OpenocdConfig,
     interface: OpenocdConfig,
     target: OpenocdConfig,
+    /// Serial number of the probe the setup is tied to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    adapter_serial: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Default)]
+pub struct GdbConfig {
+    /// Paths to GDB executables which are not in `PATH`
+    paths: Vec<String>,
 }
 
 #[derive(Clone, Serialize, Deserialize, Default)]
 pub struct AppConfig {
     openocd: OpenocdConfigSet,
     gitpod: GitpodConfig,
+    /// GDB settings, missing in dumps of the settings view
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gdb: Option<GdbConfig>,
+}
+
+impl AppConfig {
+    /// Paths to GDB executables configured by the user
+    pub fn gdb_paths(&self) -> &[String] {
+        self.gdb.as_ref().map_or(&[], |gdb| &gdb.paths)
+    }
+
+    /// Take fields which are missing in the dumped config from the stored one
+    ///
+    /// The settings view dumps only the fields it edits, so the others must
+    /// survive its saves.
+    pub fn with_stored(mut self, stored: AppConfig) -> AppConfig {
+        if self.gdb.is_none() {
+            self.gdb = stored.gdb;
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AppConfig;
+
+    const STORED: &str = r#"{
+        "openocd": {
+            "board": {"name": "", "path": ""},
+            "interface": {"name": "stlink", "path": "/usr/share/openocd/scripts/interface/stlink.cfg"},
+            "target": {"name": "stm32f1x", "path": "/usr/share/openocd/scripts/target/stm32f1x.cfg"}
+        },
+        "gitpod": {"instance_id": "", "hostname": ""},
+        "gdb": {"paths": ["/opt/gcc-arm-none-eabi/bin/arm-none-eabi-gdb"]}
+    }"#;
+
+    #[test]
+    fn dump_without_gdb_keeps_stored_paths() {
+        let dumped: AppConfig = serde_json::from_str(
+            r#"{
+                "openocd": {
+                    "board": {"name": "st_nucleo_f4", "path": "/usr/share/openocd/scripts/board/st_nucleo_f4.cfg"},
+                    "interface": {"name": "", "path": ""},
+                    "target": {"name": "", "path": ""}
+                },
+                "gitpod": {"instance_id": "", "hostname": ""}
+            }"#,
+        )
+        .unwrap();
+        let stored: AppConfig = serde_json::from_str(STORED).unwrap();
+
+        let config = dumped.with_stored(stored);
+        assert_eq!(
+            config.gdb_paths(),
+            ["/opt/gcc-arm-none-eabi/bin/arm-none-eabi-gdb"]
+        );
+        assert_eq!(config.openocd.board.name, "st_nucleo_f4");
+    }
+
+    #[test]
+    fn dump_with_gdb_replaces_stored_paths() {
+        let mut dumped: AppConfig = serde_json::from_str(STORED).unwrap();
+        dumped.gdb.as_mut().unwrap().paths.clear();
+        let stored: AppConfig = serde_json::from_str(STORED).unwrap();
+
+        assert!(dumped.with_stored(stored).gdb_paths().is_empty());
+    }
 }
@@ -0,0 +1,149 @@
+//! Decoder of `defmt` log frames using the table of the firmware ELF
+
+use defmt_decoder::{DecodeError, Frame, Locations, StreamDecoder, Table};
+use log::warn;
+use serde::Serialize;
+use std::fs;
+
+use crate::api::TauriEvent;
+use crate::error::ErrorMsg;
+
+/// Count of malformed frames in a row after which the stream is considered
+/// not matching the ELF
+const MALFORMED_LIMIT: usize = 3;
+
+/// Interned strings and locations of log statements of the firmware
+pub struct Elf {
+    path: String,
+    table: Table,
+    locations: Locations,
+}
+
+impl Elf {
+    /// Read the defmt table of the firmware ELF
+    ///
+    /// Return error if the ELF has no defmt data or it was built with an
+    /// unsupported defmt version.
+    pub fn load(path: &str) -> Result<Elf, ErrorMsg> {
+        let bytes = fs::read(path).map_err(|e| format!("Can't read ELF \"{}\": {}", path, e))?;
+
+        let table = Table::parse(&bytes)
+            .map_err(|e| format!("Can't read defmt data of ELF \"{}\": {}", path, e))?
+            .ok_or_else(|| {
+                format!(
+                    "ELF \"{}\" has no defmt data: is the firmware built with defmt?",
+                    path
+                )
+            })?;
+
+        // Locations are optional, records are decoded without them
+        let locations = table.get_locations(&bytes).unwrap_or_else(|e| {
+            warn!("No defmt locations in ELF \"{}\": {}", path, e);
+            Locations::new()
+        });
+
+        Ok(Elf {
+            path: path.to_string(),
+            table,
+            locations,
+        })
+    }
+}
+
+/// Source location of a log statement
+#[derive(Clone, Serialize)]
+pub struct Location {
+    pub file: String,
+    pub line: u64,
+    pub module: String,
+}
+
+/// Content of `app://openocd/defmt`, log record decoded from an RTT channel
+#[derive(Clone, Serialize)]
+pub struct Record {
+    pub channel: u32,
+    /// `trace`, `debug`, `info`, `warn` or `error`, `None` for `println!`
+    pub level: Option<String>,
+    pub timestamp: Option<String>,
+    pub message: String,
+    pub location: Option<Location>,
+}
+
+impl TauriEvent for Record {
+    fn topic(&self) -> &'static str {
+        "app://openocd/defmt"
+    }
+}
+
+/// Decoder of a stream of defmt frames received from a single channel
+pub struct Decoder<'a> {
+    elf: &'a Elf,
+    channel: u32,
+    stream: Box<dyn StreamDecoder + 'a>,
+    malformed: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(elf: &'a Elf, channel: u32) -> Self {
+        Decoder {
+            elf,
+            channel,
+            stream: elf.table.new_stream_decoder(),
+            malformed: 0,
+        }
+    }
+
+    /// Decode received bytes to log records
+    ///
+    /// Incomplete frame is kept until the next call. Return error if frames
+    /// don't match the ELF, e.g. the target runs another firmware.
+    pub fn feed(&mut self, data: &[u8]) -> Result<Vec<Record>, ErrorMsg> {
+        let (elf, channel) = (self.elf, self.channel);
+        let mut records = Vec::new();
+
+        self.stream.received(data);
+
+        loop {
+            match self.stream.decode() {
+                Ok(frame) => {
+                    records.push(record(elf, channel, &frame));
+                    self.malformed = 0;
+                }
+                Err(DecodeError::UnexpectedEof) => break,
+                Err(DecodeError::Malformed) => {
+                    self.malformed += 1;
+                    if self.malformed >= MALFORMED_LIMIT {
+                        return Err(format!(
+                            "defmt frames of RTT channel {} don't match ELF \"{}\": \
+                             is the target running this firmware?",
+                            channel, elf.path
+                        )
+                        .into());
+                    }
+                    // Frames after a malformed one are decoded on the next call
+                    break;
+                }
+            }
+        }
+
+        Ok(records)
+    }
+}
+
+fn record(elf: &Elf, channel: u32, frame: &Frame) -> Record {
+    let location = elf.locations.get(&frame.index()).map(|location| Location {
+        file: location.file.display().to_string(),
+        line: location.line,
+        module: location.module.clone(),
+    });
+
+    Record {
+        channel,
+        level: frame
+            .level()
+            .map(|level| format!("{:?}", level).to_lowercase()),
+        timestamp: frame.display_timestamp().map(|ts| ts.to_string()),
+        message: frame.display_message().to_string(),
+        location,
+    }
+}
@@ -0,0 +1,302 @@
+//! Discovery and launch of GDB clients attached to OpenOCD sessions
+
+use command_group::{CommandGroup, GroupChild};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdin, Command, Stdio};
+use std::thread;
+use which::which;
+
+use crate::api::TauriEvent;
+use crate::error::ErrorMsg;
+use crate::openocd::session::SessionWindow;
+
+/// Names of GDB builds which can debug ARM targets
+const KNOWN_NAMES: &[&str] = &["arm-none-eabi-gdb", "gdb-multiarch"];
+
+/// Prefix and suffix of names of RISC-V GDB builds, e.g. `riscv64-unknown-elf-gdb`
+const RISCV_PREFIX: &str = "riscv";
+const RISCV_SUFFIX: &str = "-gdb";
+
+/// Terminal emulators tried in order, with the arguments followed by a command
+///
+/// The arguments keep the terminal process alive until the command exits,
+/// otherwise the terminal hands the command off to its server and GDB can't
+/// be killed with the session. `x-terminal-emulator` is not used for this
+/// reason: its arguments depend on the terminal it points to.
+#[cfg(target_os = "linux")]
+const TERMINALS: &[(&str, &[&str])] = &[
+    ("gnome-terminal", &["--wait", "--"]),
+    ("konsole", &["--separate", "-e"]),
+    ("xfce4-terminal", &["--disable-server", "-x"]),
+    ("xterm", &["-e"]),
+];
+
+/// GDB executable found on the host
+#[derive(Clone, Debug, Serialize)]
+pub struct GdbInfo {
+    pub name: String,
+    pub path: String,
+    /// First line of `gdb --version`, `None` if GDB didn't start
+    pub version: Option<String>,
+}
+
+impl GdbInfo {
+    fn new(path: PathBuf) -> Self {
+        let version = Command::new(&path)
+            .arg("--version")
+            .output()
+            .ok()
+            .and_then(|out| {
+                String::from_utf8_lossy(&out.stdout)
+                    .lines()
+                    .next()
+                    .map(str::to_string)
+            });
+
+        GdbInfo {
+            name: path
+                .file_stem()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            path: path.to_string_lossy().to_string(),
+            version,
+        }
+    }
+}
+
+/// Find GDB executables
+///
+/// Configured paths go first, then known GDB builds found in `PATH`.
+/// Configured paths which are not files are skipped.
+pub fn find(paths: &[String]) -> Vec<GdbInfo> {
+    let mut found: Vec<PathBuf> = paths
+        .iter()
+        .map(PathBuf::from)
+        .filter(|path| path.is_file())
+        .collect();
+
+    found.extend(KNOWN_NAMES.iter().filter_map(|name| which(name).ok()));
+    found.extend(riscv_builds());
+
+    let mut seen = HashSet::new();
+    found
+        .into_iter()
+        .filter(|path| seen.insert(path.clone()))
+        .map(GdbInfo::new)
+        .collect()
+}
+
+/// Find `riscv*-gdb` executables in directories of `PATH`
+fn riscv_builds() -> Vec<PathBuf> {
+    let dirs = match env::var_os("PATH") {
+        Some(dirs) => env::split_paths(&dirs).collect::<Vec<PathBuf>>(),
+        None => return Vec::new(),
+    };
+
+    let mut builds: Vec<PathBuf> = dirs
+        .iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(Result::ok))
+        .map(|entry| entry.path())
+        .filter(|path| {
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            let is_exe = match path.extension() {
+                Some(ext) => cfg!(windows) && ext.eq_ignore_ascii_case("exe"),
+                None => true,
+            };
+            is_exe && stem.starts_with(RISCV_PREFIX) && stem.ends_with(RISCV_SUFFIX)
+        })
+        .filter(|path| path.is_file())
+        .collect();
+
+    builds.sort();
+    builds
+}
+
+/// Parameters of a GDB launch
+#[derive(Clone, Debug, Deserialize)]
+pub struct GdbLaunch {
+    /// Path to GDB, the first found GDB is used if not provided
+    pub path: Option<String>,
+    /// Firmware ELF to load symbols from
+    pub elf: Option<String>,
+    /// Commands run after GDB is attached to the target
+    #[serde(default)]
+    pub init: Vec<String>,
+    /// Run GDB in a terminal window instead of the background
+    #[serde(default)]
+    pub terminal: bool,
+}
+
+/// Content of `app://gdb/output`, line of output of a headless GDB
+#[derive(Clone, Serialize)]
+pub struct GdbOutput(pub String);
+
+impl TauriEvent for GdbOutput {
+    fn topic(&self) -> &'static str {
+        "app://gdb/output"
+    }
+}
+
+/// GDB attached to the gdb server of an OpenOCD session
+///
+/// GDB with its terminal is killed on drop, so it doesn't outlive the
+/// session. Headless GDB output is emitted as `app://gdb/output` event of
+/// the session.
+pub struct Gdb {
+    proc: GroupChild,
+    /// Stdin of a headless GDB, kept open so GDB doesn't exit on EOF
+    _stdin: Option<ChildStdin>,
+}
+
+impl Gdb {
+    /// Start GDB attached to the gdb server on `gdb_port`
+    ///
+    /// If `launch` has no GDB path, the first GDB found in `paths` or in
+    /// `PATH` is used.
+    pub fn launch(
+        gdb_port: u16,
+        launch: &GdbLaunch,
+        paths: &[String],
+        window: SessionWindow,
+    ) -> Result<Gdb, ErrorMsg> {
+        let path = match &launch.path {
+            Some(path) => PathBuf::from(path),
+            None => find(paths)
+                .into_iter()
+                .next()
+                .map(|gdb| PathBuf::from(gdb.path))
+                .ok_or_else(|| ErrorMsg::from("GDB not found!"))?,
+        };
+
+        if let Some(elf) = &launch.elf {
+            if !Path::new(elf).is_file() {
+                return Err(format!("ELF \"{}\" not found!", elf).into());
+            }
+        }
+
+        let args = Self::args(gdb_port, launch);
+        info!("Launch GDB: {} {:?}", path.display(), args);
+
+        if launch.terminal {
+            let proc = Self::spawn_in_terminal(&path, &args)?;
+            Ok(Gdb { proc, _stdin: None })
+        } else {
+            let mut proc = Command::new(&path)
+                .args(&args)
+                .stdin(Stdio::piped())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .group_spawn()
+                .map_err(|e| format!("Can't start GDB \"{}\": {}", path.display(), e))?;
+
+            let stdin = proc.inner().stdin.take();
+            if let Some(stdout) = proc.inner().stdout.take() {
+                let window = window.clone();
+                thread::spawn(move || Self::read_output(stdout, window));
+            }
+            if let Some(stderr) = proc.inner().stderr.take() {
+                thread::spawn(move || Self::read_output(stderr, window));
+            }
+
+            Ok(Gdb {
+                proc,
+                _stdin: stdin,
+            })
+        }
+    }
+
+    fn args(gdb_port: u16, launch: &GdbLaunch) -> Vec<String> {
+        let mut commands = vec![format!("target extended-remote :{}", gdb_port)];
+        commands.extend(launch.init.iter().cloned());
+
+        let mut args: Vec<String> = commands
+            .into_iter()
+            .flat_map(|command| vec!["-ex".to_string(), command])
+            .collect();
+
+        if let Some(elf) = &launch.elf {
+            args.push(elf.clone());
+        }
+        args
+    }
+
+    #[cfg(target_os = "linux")]
+    fn spawn_in_terminal(path: &Path, args: &[String]) -> Result<GroupChild, ErrorMsg> {
+        let (terminal, exec_args) = TERMINALS
+            .iter()
+            .find(|(terminal, _)| which(terminal).is_ok())
+            .ok_or_else(|| ErrorMsg::from("No terminal emulator found to run GDB in!"))?;
+
+        Command::new(terminal)
+            .args(*exec_args)
+            .arg(path)
+            .args(args)
+            .group_spawn()
+            .map_err(|e| format!("Can't start GDB in \"{}\": {}", terminal, e).into())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn spawn_in_terminal(path: &Path, args: &[String]) -> Result<GroupChild, ErrorMsg> {
+        use std::os::windows::process::CommandExt;
+
+        // `start /WAIT` keeps `cmd` alive until GDB exits, so GDB is killed with its group.
+        // The first quoted argument of `start` is the title, `arg` doesn't quote it.
+        Command::new("cmd")
+            .args(["/C", "start"])
+            .raw_arg("\"GDB\"")
+            .arg("/WAIT")
+            .arg(path)
+            .args(args)
+            .group_spawn()
+            .map_err(|e| format!("Can't start GDB in a console: {}", e).into())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    fn spawn_in_terminal(_path: &Path, _args: &[String]) -> Result<GroupChild, ErrorMsg> {
+        Err("GDB in a terminal is not supported on this OS!".into())
+    }
+
+    fn read_output(stream: impl std::io::Read, window: SessionWindow) {
+        let reader = BufReader::new(stream);
+
+        for line in reader.lines() {
+            match line {
+                Ok(line) => window.send(GdbOutput(format!("{}\n", line))),
+                Err(e) => {
+                    error!("GDB output read failed: {}", e);
+                    break;
+                }
+            }
+        }
+
+        info!("GDB output closed.");
+    }
+
+    /// Return `true` if GDB or its terminal is still running
+    pub fn is_running(&mut self) -> bool {
+        matches!(self.proc.try_wait(), Ok(None))
+    }
+}
+
+impl Drop for Gdb {
+    fn drop(&mut self) {
+        if !self.is_running() {
+            return;
+        }
+
+        match self.proc.kill().and_then(|_| self.proc.wait()) {
+            Ok(_) => info!("GDB stopped."),
+            Err(e) => warn!("GDB was not stopped: {}", e),
+        }
+    }
+}
@@ -7,11 +7,14 @@ mod api;
 mod app;
 mod cmd;
 mod config;
+mod defmt;
 mod error;
+mod gdb;
 mod notification;
 mod openocd;
 mod state;
 mod gitpod;
+mod svd;
 
 use crate::app::App;
 
@@ -19,6 +22,8 @@ fn main() {
     ::std::env::set_var("RUST_LOG", "debug");
     env_logger::init();
 
+    openocd::version::Capabilities::detect_in_background();
+
     tauri::Builder::default()
         .manage(state::State {
             app: App::new(),
@@ -27,10 +32,47 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             cmd::start,
             cmd::kill,
+            cmd::start_session,
+            cmd::kill_session,
+            cmd::list_sessions,
             cmd::load_state,
             cmd::dump_state,
             cmd::get_config_lists,
+            cmd::list_probes,
             cmd::start_gitpod,
+            cmd::send_tcl,
+            cmd::program,
+            cmd::halt,
+            cmd::resume,
+            cmd::step,
+            cmd::reset,
+            cmd::list_flash_banks,
+            cmd::flash_info,
+            cmd::mass_erase,
+            cmd::erase_sectors,
+            cmd::set_write_protection,
+            cmd::set_read_protection,
+            cmd::read_memory,
+            cmd::write_memory,
+            cmd::load_svd,
+            cmd::read_peripheral,
+            cmd::open_console,
+            cmd::console_send,
+            cmd::console_history,
+            cmd::close_console,
+            cmd::start_rtt,
+            cmd::rtt_send,
+            cmd::stop_rtt,
+            cmd::start_swo,
+            cmd::stop_swo,
+            cmd::find_gdb,
+            cmd::launch_gdb,
+            cmd::stop_gdb,
+            cmd::get_target_state,
+            cmd::get_capabilities,
+            cmd::get_config_tree,
+            cmd::get_board_implied,
+            cmd::filter_configs,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,618 @@
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+use crate::error::ErrorMsg;
+use crate::openocd::rpc::TclClient;
+
+/// Time to wait for an erase or a protection change, mass erase may be slow
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(120);
+
+lazy_static! {
+    static ref BANK: Regex = Regex::new(
+        r"^#(\d+)\s*: (\S+) \((\S+)\) at (0x[0-9a-fA-F]+), size (0x[0-9a-fA-F]+), buswidth (\d+), chipwidth (\d+)"
+    )
+    .unwrap();
+    static ref SECTOR: Regex =
+        Regex::new(r"^#\s*(\d+): (0x[0-9a-fA-F]+) \((0x[0-9a-fA-F]+) \d+kB\) (.+)$").unwrap();
+}
+
+/// Flash drivers with their own commands for whole bank operations
+struct DriverCommands {
+    driver: &'static str,
+    /// `<driver> mass_erase <bank>` is available
+    mass_erase: bool,
+    /// `<driver> lock|unlock <bank>` sets or clears read protection
+    lock: bool,
+}
+
+const DRIVER_COMMANDS: &[DriverCommands] = &[
+    DriverCommands {
+        driver: "stm32f1x",
+        mass_erase: true,
+        lock: true,
+    },
+    DriverCommands {
+        driver: "stm32f2x",
+        mass_erase: true,
+        lock: true,
+    },
+    DriverCommands {
+        driver: "stm32l4x",
+        mass_erase: true,
+        lock: true,
+    },
+    DriverCommands {
+        driver: "stm32h7x",
+        mass_erase: true,
+        lock: true,
+    },
+    DriverCommands {
+        driver: "stm32lx",
+        mass_erase: true,
+        lock: true,
+    },
+];
+
+impl DriverCommands {
+    fn find(driver: &str) -> Option<&'static DriverCommands> {
+        DRIVER_COMMANDS.iter().find(|known| known.driver == driver)
+    }
+}
+
+/// Flash bank declared by the target config
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FlashBank {
+    pub index: u32,
+    pub name: String,
+    pub driver: String,
+    pub base: u64,
+    pub size: u64,
+    pub bus_width: u32,
+    pub chip_width: u32,
+}
+
+/// Protection state of a flash sector
+#[derive(Clone, Copy, Debug, PartialEq, serde_repr::Serialize_repr)]
+#[repr(u8)]
+pub enum Protection {
+    Unknown = 0,
+    Unprotected = 1,
+    Protected = 2,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Sector {
+    pub index: u32,
+    /// Offset from the base of the bank
+    pub offset: u64,
+    pub size: u64,
+    pub protection: Protection,
+}
+
+/// Result of `flash info`
+#[derive(Clone, Debug, Serialize)]
+pub struct BankInfo {
+    pub bank: FlashBank,
+    pub sectors: Vec<Sector>,
+    /// Lines of driver specific info, e.g. the chip revision
+    pub details: Vec<String>,
+}
+
+/// Operation which changes the flash content or protection
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FlashOperation {
+    MassErase {
+        bank: u32,
+    },
+    EraseSectors {
+        bank: u32,
+        first: u32,
+        last: u32,
+    },
+    /// Protect sectors from writes or remove the protection
+    WriteProtect {
+        bank: u32,
+        first: u32,
+        last: u32,
+        enable: bool,
+    },
+    /// Lock the chip against reads by a debugger, unlock erases the chip
+    ReadProtect {
+        bank: u32,
+        enable: bool,
+    },
+}
+
+impl FlashOperation {
+    fn bank(&self) -> u32 {
+        match self {
+            FlashOperation::MassErase { bank }
+            | FlashOperation::EraseSectors { bank, .. }
+            | FlashOperation::WriteProtect { bank, .. }
+            | FlashOperation::ReadProtect { bank, .. } => *bank,
+        }
+    }
+
+    /// Return the OpenOCD command with a description of its effect
+    fn plan(&self, info: &BankInfo) -> Result<(String, String), ErrorMsg> {
+        let bank = &info.bank;
+        let driver = DriverCommands::find(&bank.driver);
+        let describe = format!(
+            "bank {} ({}, {} KiB at {:#010x})",
+            bank.index,
+            bank.name,
+            bank.size / 1024,
+            bank.base
+        );
+
+        let plan = match self {
+            FlashOperation::MassErase { .. } => {
+                let command = match driver {
+                    Some(driver) if driver.mass_erase => {
+                        format!("{} mass_erase {}", bank.driver, bank.index)
+                    }
+                    _ => format!("flash erase_sector {} 0 last", bank.index),
+                };
+                (
+                    command,
+                    format!("Erase whole {}? All its data will be lost.", describe),
+                )
+            }
+            FlashOperation::EraseSectors { first, last, .. } => {
+                check_sectors(info, *first, *last)?;
+                (
+                    format!("flash erase_sector {} {} {}", bank.index, first, last),
+                    format!(
+                        "Erase sectors {}-{} of {}? Their data will be lost.",
+                        first, last, describe
+                    ),
+                )
+            }
+            FlashOperation::WriteProtect {
+                first,
+                last,
+                enable,
+                ..
+            } => {
+                check_sectors(info, *first, *last)?;
+                let (state, action) = if *enable {
+                    ("on", "Protect")
+                } else {
+                    ("off", "Remove write protection of")
+                };
+                (
+                    format!("flash protect {} {} {} {}", bank.index, first, last, state),
+                    format!("{} sectors {}-{} of {}?", action, first, last, describe),
+                )
+            }
+            FlashOperation::ReadProtect { enable, .. } => {
+                if !matches!(driver, Some(driver) if driver.lock) {
+                    return Err(format!(
+                        "Read protection is not supported by flash driver \"{}\"!",
+                        bank.driver
+                    )
+                    .into());
+                }
+                if *enable {
+                    (
+                        format!("{} lock {}", bank.driver, bank.index),
+                        format!(
+                            "Lock {} against reads? The debugger won't read the flash until it is unlocked.",
+                            describe
+                        ),
+                    )
+                } else {
+                    (
+                        format!("{} unlock {}", bank.driver, bank.index),
+                        format!(
+                            "Unlock {}? The chip will be mass erased, all data will be lost.",
+                            describe
+                        ),
+                    )
+                }
+            }
+        };
+
+        Ok(plan)
+    }
+
+    /// Hint on what to do after the operation, if anything
+    fn note(&self) -> Option<String> {
+        match self {
+            FlashOperation::ReadProtect { .. } => {
+                Some("Power cycle or reset the target to apply the new protection.".into())
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Result of a flash operation
+///
+/// An operation is run only if it was confirmed, otherwise a message to
+/// show in the confirmation dialog is returned with the planned command.
+/// The command must be passed back to run the operation, so a confirmation
+/// is valid for the same operation of the same bank only.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    Confirm {
+        message: String,
+        /// OpenOCD command to run after the confirmation
+        command: String,
+    },
+    Done {
+        /// OpenOCD command which was run
+        command: String,
+        output: String,
+        duration_ms: u64,
+        note: Option<String>,
+    },
+}
+
+/// Return flash banks of all targets
+pub fn list(tcl: &mut TclClient) -> Result<Vec<FlashBank>, ErrorMsg> {
+    let output = tcl.execute_checked("flash banks")?;
+    Ok(output.lines().filter_map(parse_bank).collect())
+}
+
+/// Probe a bank and return its sectors
+pub fn info(tcl: &mut TclClient, bank: u32) -> Result<BankInfo, ErrorMsg> {
+    let banks = list(tcl)?;
+    let found = banks
+        .into_iter()
+        .find(|found| found.index == bank)
+        .ok_or_else(|| ErrorMsg::from(format!("Flash bank {} not found!", bank)))?;
+
+    let output = tcl
+        .execute_checked(&format!("flash info {}", bank))
+        .map_err(|e| {
+            format!(
+                "Can't read info of flash bank {}: {}",
+                bank,
+                e.message.trim()
+            )
+        })?;
+
+    Ok(parse_info(found, &output))
+}
+
+/// Run a flash operation if it is confirmed
+///
+/// `confirmed` is the command returned by `Outcome::Confirm` of the same
+/// operation. The operation is planned again, so it is not run if the
+/// command differs, e.g. the bank was replaced in between. The target is
+/// halted first, since flash drivers run algorithms on it.
+pub fn run(
+    tcl: &mut TclClient,
+    operation: &FlashOperation,
+    confirmed: Option<&str>,
+) -> Result<Outcome, ErrorMsg> {
+    let info = info(tcl, operation.bank())?;
+    let (command, message) = operation.plan(&info)?;
+
+    if let Some(outcome) = confirmation(command.clone(), message, confirmed)? {
+        return Ok(outcome);
+    }
+
+    info!("Flash operation: {}", command);
+    let started_at = Instant::now();
+
+    tcl.execute_checked("halt")
+        .map_err(|e| format!("Can't halt the target: {}", e.message.trim()))?;
+    tcl.set_read_timeout(OPERATION_TIMEOUT)?;
+    let output = tcl
+        .execute_checked(&command)
+        .map_err(|e| format!("\"{}\" failed: {}", command, e.message.trim()))?;
+
+    Ok(Outcome::Done {
+        command,
+        output: output.trim().to_string(),
+        duration_ms: started_at.elapsed().as_millis() as u64,
+        note: operation.note(),
+    })
+}
+
+/// Return the outcome to confirm if the planned command was not confirmed
+fn confirmation(
+    command: String,
+    message: String,
+    confirmed: Option<&str>,
+) -> Result<Option<Outcome>, ErrorMsg> {
+    match confirmed {
+        None => Ok(Some(Outcome::Confirm { message, command })),
+        Some(confirmed) if confirmed == command => Ok(None),
+        Some(confirmed) => Err(format!(
+            "Flash operation is not confirmed: \"{}\" was confirmed, but \"{}\" is planned!",
+            confirmed, command
+        )
+        .into()),
+    }
+}
+
+fn check_sectors(info: &BankInfo, first: u32, last: u32) -> Result<(), ErrorMsg> {
+    let count = info.sectors.len() as u32;
+
+    if first > last || last >= count {
+        Err(format!(
+            "Bad sector range {}-{}: bank {} has sectors 0-{}!",
+            first,
+            last,
+            info.bank.index,
+            count.saturating_sub(1)
+        )
+        .into())
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_hex(value: &str) -> u64 {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).unwrap_or_default()
+}
+
+/// Parse the output of `flash info`
+///
+/// Lines which are not sectors or the bank header are driver specific
+/// details.
+fn parse_info(bank: FlashBank, output: &str) -> BankInfo {
+    let mut sectors = Vec::new();
+    let mut details = Vec::new();
+
+    for line in output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+    {
+        if let Some(sector) = parse_sector(line) {
+            sectors.push(sector);
+        } else if !line.starts_with('#') {
+            details.push(line.to_string());
+        }
+    }
+
+    BankInfo {
+        bank,
+        sectors,
+        details,
+    }
+}
+
+/// Parse a line of `flash banks`
+///
+/// ```text
+/// #0 : stm32f1x.flash (stm32f1x) at 0x08000000, size 0x00020000, buswidth 0, chipwidth 0
+/// ```
+fn parse_bank(line: &str) -> Option<FlashBank> {
+    let caps = BANK.captures(line.trim())?;
+
+    Some(FlashBank {
+        index: caps[1].parse().ok()?,
+        name: caps[2].to_string(),
+        driver: caps[3].to_string(),
+        base: parse_hex(&caps[4]),
+        size: parse_hex(&caps[5]),
+        bus_width: caps[6].parse().unwrap_or_default(),
+        chip_width: caps[7].parse().unwrap_or_default(),
+    })
+}
+
+/// Parse a sector line of `flash info`
+///
+/// ```text
+/// #  3: 0x00000c00 (0x400 1kB) not protected
+/// ```
+fn parse_sector(line: &str) -> Option<Sector> {
+    let caps = SECTOR.captures(line)?;
+
+    let protection = match caps[4].trim() {
+        "protected" => Protection::Protected,
+        "not protected" => Protection::Unprotected,
+        _ => Protection::Unknown,
+    };
+
+    Some(Sector {
+        index: caps[1].parse().ok()?,
+        offset: parse_hex(&caps[2]),
+        size: parse_hex(&caps[3]),
+        protection,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        confirmation, parse_bank, parse_info, FlashBank, FlashOperation, Outcome, Protection,
+        Sector,
+    };
+
+    /// `flash banks` of a board with an STM32F103 and an external NOR flash
+    const BANKS: &str = "\
+#0 : stm32f1x.flash (stm32f1x) at 0x08000000, size 0x00020000, buswidth 0, chipwidth 0
+#1 : nor.flash (cfi) at 0x60000000, size 0x01000000, buswidth 2, chipwidth 2
+";
+
+    /// `flash info 0` of an STM32F103C8 in the format of OpenOCD 0.11, cut
+    const INFO_0_11: &str = "\
+#0 : stm32f1x at 0x08000000, size 0x00020000, buswidth 0, chipwidth 0
+\t#  0: 0x00000000 (0x400 1kB) not protected
+\t#  1: 0x00000400 (0x400 1kB) not protected
+\t#  2: 0x00000800 (0x400 1kB) protected
+STM32F10x (Medium Density) - Rev: X
+";
+
+    /// `flash info 0` of an STM32F407 in the format of OpenOCD 0.12, the
+    /// driver can't read protection of sectors of a locked chip
+    const INFO_0_12: &str = "\
+#0 : stm32f2x at 0x08000000, size 0x00100000, buswidth 0, chipwidth 0
+\t#  0: 0x00000000 (0x4000 16kB) protection state unknown
+\t#  4: 0x00010000 (0x10000 64kB) protection state unknown
+\t# 11: 0x000e0000 (0x20000 128kB) protection state unknown
+STM32F4xx - Rev: Z
+";
+
+    fn stm32f1x() -> FlashBank {
+        parse_bank(BANKS.lines().next().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn banks() {
+        let banks: Vec<FlashBank> = BANKS.lines().filter_map(parse_bank).collect();
+
+        assert_eq!(
+            banks,
+            vec![
+                FlashBank {
+                    index: 0,
+                    name: "stm32f1x.flash".into(),
+                    driver: "stm32f1x".into(),
+                    base: 0x0800_0000,
+                    size: 0x2_0000,
+                    bus_width: 0,
+                    chip_width: 0,
+                },
+                FlashBank {
+                    index: 1,
+                    name: "nor.flash".into(),
+                    driver: "cfi".into(),
+                    base: 0x6000_0000,
+                    size: 0x100_0000,
+                    bus_width: 2,
+                    chip_width: 2,
+                },
+            ]
+        );
+        assert_eq!(parse_bank("No flash banks found"), None);
+    }
+
+    #[test]
+    fn sectors() {
+        let info = parse_info(stm32f1x(), INFO_0_11);
+
+        assert_eq!(
+            info.sectors,
+            vec![
+                Sector {
+                    index: 0,
+                    offset: 0,
+                    size: 0x400,
+                    protection: Protection::Unprotected,
+                },
+                Sector {
+                    index: 1,
+                    offset: 0x400,
+                    size: 0x400,
+                    protection: Protection::Unprotected,
+                },
+                Sector {
+                    index: 2,
+                    offset: 0x800,
+                    size: 0x400,
+                    protection: Protection::Protected,
+                },
+            ]
+        );
+        // The bank header is not a detail
+        assert_eq!(info.details, vec!["STM32F10x (Medium Density) - Rev: X"]);
+    }
+
+    #[test]
+    fn unknown_protection() {
+        let info = parse_info(stm32f1x(), INFO_0_12);
+
+        let sectors: Vec<(u32, u64, u64)> = info
+            .sectors
+            .iter()
+            .map(|sector| (sector.index, sector.offset, sector.size))
+            .collect();
+        assert_eq!(
+            sectors,
+            vec![
+                (0, 0, 0x4000),
+                (4, 0x1_0000, 0x1_0000),
+                (11, 0xe_0000, 0x2_0000)
+            ]
+        );
+        assert!(info
+            .sectors
+            .iter()
+            .all(|sector| sector.protection == Protection::Unknown));
+        assert_eq!(info.details, vec!["STM32F4xx - Rev: Z"]);
+    }
+
+    #[test]
+    fn plans() {
+        let info = parse_info(stm32f1x(), INFO_0_11);
+        let command = |operation: FlashOperation| operation.plan(&info).map(|plan| plan.0);
+
+        assert_eq!(
+            command(FlashOperation::MassErase { bank: 0 }).unwrap(),
+            "stm32f1x mass_erase 0"
+        );
+        assert_eq!(
+            command(FlashOperation::EraseSectors {
+                bank: 0,
+                first: 1,
+                last: 2
+            })
+            .unwrap(),
+            "flash erase_sector 0 1 2"
+        );
+        assert_eq!(
+            command(FlashOperation::WriteProtect {
+                bank: 0,
+                first: 0,
+                last: 0,
+                enable: false
+            })
+            .unwrap(),
+            "flash protect 0 0 0 off"
+        );
+        assert_eq!(
+            command(FlashOperation::ReadProtect {
+                bank: 0,
+                enable: true
+            })
+            .unwrap(),
+            "stm32f1x lock 0"
+        );
+        assert_eq!(
+            command(FlashOperation::EraseSectors {
+                bank: 0,
+                first: 2,
+                last: 3
+            })
+            .unwrap_err()
+            .message,
+            "Bad sector range 2-3: bank 0 has sectors 0-2!"
+        );
+    }
+
+    #[test]
+    fn confirmations() {
+        let planned = || ("flash erase_sector 0 1 2".to_string(), "Erase?".to_string());
+
+        let (command, message) = planned();
+        assert_eq!(
+            confirmation(command, message, None).unwrap(),
+            Some(Outcome::Confirm {
+                message: "Erase?".into(),
+                command: "flash erase_sector 0 1 2".into(),
+            })
+        );
+
+        let (command, message) = planned();
+        assert_eq!(
+            confirmation(command, message, Some("flash erase_sector 0 1 2")).unwrap(),
+            None
+        );
+
+        // Confirmation of another operation doesn't run this one
+        let (command, message) = planned();
+        assert!(confirmation(command, message, Some("flash erase_sector 0 1 1")).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+use serde::Serialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ErrorMsg;
+use crate::openocd::config::Config;
+use crate::openocd::paths::OpenocdPaths;
+use crate::openocd::script::{self, ConfigTree};
+
+/// Interface and target configs sourced by a board config
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Implied {
+    pub interface: Option<Config>,
+    pub target: Option<Config>,
+    /// Selected configs which repeat or contradict configs of the board
+    pub warnings: Vec<String>,
+}
+
+impl Implied {
+    fn from_tree(tree: &ConfigTree, paths: &OpenocdPaths) -> Self {
+        let included: Vec<PathBuf> = tree
+            .included()
+            .iter()
+            .map(|include| PathBuf::from(&include.path))
+            .collect();
+
+        // The first include is the one sourced by the board itself, the
+        // others are usually helpers sourced by it
+        let find = |dir: &Path| {
+            included
+                .iter()
+                .filter(|path| path.starts_with(dir))
+                .find_map(|path| Config::from_path(dir, path))
+        };
+
+        Implied {
+            interface: find(&paths.interface),
+            target: find(&paths.target),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Warn about every selected interface or target config which is already
+    /// sourced by the board or differs from the one the board sources
+    ///
+    /// Configs out of the script folder of OpenOCD are not checked.
+    fn check(
+        &mut self,
+        board: &Config,
+        tree: &ConfigTree,
+        selected: &[Config],
+        paths: &OpenocdPaths,
+    ) {
+        for config in selected {
+            let path = Path::new(&config.path);
+            let (kind, sourced) = if path.starts_with(&paths.interface) {
+                ("Interface", &self.interface)
+            } else if path.starts_with(&paths.target) {
+                ("Target", &self.target)
+            } else {
+                continue;
+            };
+
+            let is_included = tree
+                .included()
+                .iter()
+                .any(|include| is_same_file(Path::new(&include.path), path));
+
+            if is_included {
+                self.warnings.push(format!(
+                    "{} \"{}\" is already sourced by board \"{}\"",
+                    kind, config.name, board.name
+                ));
+            } else if let Some(sourced) = sourced {
+                self.warnings.push(format!(
+                    "{} \"{}\" conflicts with \"{}\" sourced by board \"{}\"",
+                    kind, config.name, sourced.name, board.name
+                ));
+            }
+        }
+    }
+}
+
+/// Return interface and target configs a board config sources
+///
+/// `selected` configs are checked against them, so a combination which
+/// repeats or contradicts the board is warned about before the start.
+pub fn implied(board: &Config, selected: &[Config]) -> Result<Implied, ErrorMsg> {
+    let paths = OpenocdPaths::new()?;
+    implied_in(board, selected, &paths)
+}
+
+fn implied_in(
+    board: &Config,
+    selected: &[Config],
+    paths: &OpenocdPaths,
+) -> Result<Implied, ErrorMsg> {
+    let tree = script::scan_file(Path::new(&board.path), &paths.scripts)?;
+
+    let mut implied = Implied::from_tree(&tree, paths);
+    implied.check(board, &tree, selected, paths);
+    Ok(implied)
+}
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::implied_in;
+    use crate::openocd::config::Config;
+    use crate::openocd::paths::OpenocdPaths;
+    use crate::openocd::testing::TempDir;
+
+    /// Scripts folder with a board which sources an ST-LINK and STM32F4
+    fn scripts() -> (TempDir, OpenocdPaths) {
+        let scripts = TempDir::new("board");
+        scripts.write("interface/stlink.cfg", "adapter driver hla\n");
+        scripts.write("interface/jlink.cfg", "adapter driver jlink\n");
+        scripts.write("target/swj-dp.tcl", "# helper\n");
+        scripts.write(
+            "target/stm32f4x.cfg",
+            "source [find target/swj-dp.tcl]\nset _CHIPNAME stm32f4x\n",
+        );
+        scripts.write("target/stm32f1x.cfg", "set _CHIPNAME stm32f1x\n");
+        scripts.write(
+            "board/stm32f4discovery.cfg",
+            "source [find interface/stlink.cfg]\n\
+             transport select hla_swd\n\
+             source [find target/stm32f4x.cfg]\n",
+        );
+        scripts.write("board/bare.cfg", "adapter speed 1000\n");
+
+        let paths = OpenocdPaths::with_scripts(scripts.path().to_path_buf());
+        (scripts, paths)
+    }
+
+    fn config(paths: &OpenocdPaths, name: &str) -> Config {
+        Config::from_path(&paths.scripts, &paths.scripts.join(format!("{}.cfg", name))).unwrap()
+    }
+
+    #[test]
+    fn implied_configs() {
+        let (_scripts, paths) = scripts();
+        let board = config(&paths, "board/stm32f4discovery");
+
+        let implied = implied_in(&board, &[], &paths).unwrap();
+        assert_eq!(implied.interface.unwrap().name, "stlink");
+        // Helpers sourced by the target are not the target
+        assert_eq!(implied.target.unwrap().name, "stm32f4x");
+        assert!(implied.warnings.is_empty());
+
+        let implied = implied_in(&config(&paths, "board/bare"), &[], &paths).unwrap();
+        assert!(implied.interface.is_none());
+        assert!(implied.target.is_none());
+    }
+
+    #[test]
+    fn already_sourced() {
+        let (_scripts, paths) = scripts();
+        let board = config(&paths, "board/stm32f4discovery");
+        let selected = [
+            config(&paths, "interface/stlink"),
+            config(&paths, "target/stm32f4x"),
+        ];
+
+        let implied = implied_in(&board, &selected, &paths).unwrap();
+        assert_eq!(
+            implied.warnings,
+            vec![
+                "Interface \"interface/stlink\" is already sourced by board \"board/stm32f4discovery\"",
+                "Target \"target/stm32f4x\" is already sourced by board \"board/stm32f4discovery\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn conflicts() {
+        let (_scripts, paths) = scripts();
+        let board = config(&paths, "board/stm32f4discovery");
+        let selected = [
+            config(&paths, "interface/jlink"),
+            config(&paths, "target/stm32f1x"),
+        ];
+
+        let implied = implied_in(&board, &selected, &paths).unwrap();
+        assert_eq!(
+            implied.warnings,
+            vec![
+                "Interface \"interface/jlink\" conflicts with \"stlink\" sourced by board \"board/stm32f4discovery\"",
+                "Target \"target/stm32f1x\" conflicts with \"stm32f4x\" sourced by board \"board/stm32f4discovery\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn unrelated_configs() {
+        let (_scripts, paths) = scripts();
+        let own = TempDir::new("board-own");
+        let selected = [
+            config(&paths, "interface/jlink"),
+            Config {
+                name: "stlink".into(),
+                path: own
+                    .write("stlink.cfg", "adapter driver hla\n")
+                    .display()
+                    .to_string(),
+                ..Config::default()
+            },
+        ];
+
+        // A board which sources nothing doesn't conflict
+        let implied = implied_in(&config(&paths, "board/bare"), &selected[..1], &paths).unwrap();
+        assert!(implied.warnings.is_empty());
+
+        // Configs out of the scripts folder are not checked
+        let board = config(&paths, "board/stm32f4discovery");
+        let implied = implied_in(&board, &selected[1..], &paths).unwrap();
+        assert!(implied.warnings.is_empty());
+    }
+}
@@ -0,0 +1,379 @@
+//! Metadata of cfg files derived from their content and names
+//!
+//! The adapter driver and the CPU architecture are taken from the commands
+//! found by the script scanner. The vendor is guessed from words of the
+//! config name and of the chip name, e.g. `st_nucleo_f4` or `stm32f4x`.
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::openocd::config::Config;
+use crate::openocd::probe::Driver;
+use crate::openocd::script::{self, ConfigFacts};
+
+/// CPU architecture of a target
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Arch {
+    CortexM,
+    CortexA,
+    CortexR,
+    RiscV,
+    Xtensa,
+}
+
+impl Arch {
+    /// Map a target type of `target create` to the architecture
+    fn from_target_type(kind: &str) -> Option<Arch> {
+        match kind {
+            "cortex_m" | "hla_target" => Some(Arch::CortexM),
+            "cortex_a" | "aarch64" => Some(Arch::CortexA),
+            "cortex_r4" => Some(Arch::CortexR),
+            "riscv" => Some(Arch::RiscV),
+            "xtensa" | "esp32" | "esp32s2" | "esp32s3" => Some(Arch::Xtensa),
+            _ => None,
+        }
+    }
+}
+
+enum Token {
+    /// The word equals the keyword
+    Exact(&'static str),
+    /// The word starts with the keyword
+    Prefix(&'static str),
+}
+
+struct Vendor {
+    name: &'static str,
+    tokens: &'static [Token],
+}
+
+const VENDORS: &[Vendor] = &[
+    Vendor {
+        name: "STMicroelectronics",
+        tokens: &[
+            Token::Exact("st"),
+            Token::Exact("nucleo"),
+            Token::Prefix("stm32"),
+            Token::Prefix("stm8"),
+            Token::Prefix("bluenrg"),
+            Token::Prefix("spear"),
+        ],
+    },
+    Vendor {
+        name: "Nordic Semiconductor",
+        tokens: &[Token::Exact("nordic"), Token::Prefix("nrf")],
+    },
+    Vendor {
+        name: "Espressif",
+        tokens: &[Token::Exact("espressif"), Token::Prefix("esp32")],
+    },
+    Vendor {
+        name: "Microchip",
+        tokens: &[
+            Token::Exact("atmel"),
+            Token::Exact("microchip"),
+            Token::Prefix("at91"),
+            Token::Prefix("atsam"),
+            Token::Prefix("sam3"),
+            Token::Prefix("sam4"),
+            Token::Prefix("samd"),
+            Token::Prefix("same"),
+            Token::Prefix("saml"),
+            Token::Prefix("samv"),
+        ],
+    },
+    Vendor {
+        name: "NXP",
+        tokens: &[
+            Token::Exact("nxp"),
+            Token::Exact("frdm"),
+            Token::Prefix("lpc"),
+            Token::Prefix("imx"),
+            Token::Prefix("mimxrt"),
+            Token::Prefix("kinetis"),
+            Token::Prefix("s32"),
+        ],
+    },
+    Vendor {
+        name: "Texas Instruments",
+        tokens: &[
+            Token::Exact("ti"),
+            Token::Prefix("tm4c"),
+            Token::Prefix("lm3s"),
+            Token::Prefix("lm4f"),
+            Token::Prefix("msp"),
+            Token::Prefix("cc13"),
+            Token::Prefix("cc26"),
+            Token::Prefix("cc32"),
+            Token::Prefix("omap"),
+            Token::Prefix("am335"),
+        ],
+    },
+    Vendor {
+        name: "Silicon Labs",
+        tokens: &[
+            Token::Exact("silabs"),
+            Token::Prefix("efm32"),
+            Token::Prefix("efr32"),
+        ],
+    },
+    Vendor {
+        name: "Infineon",
+        tokens: &[
+            Token::Exact("infineon"),
+            Token::Exact("cypress"),
+            Token::Prefix("psoc"),
+            Token::Prefix("xmc"),
+        ],
+    },
+    Vendor {
+        name: "GigaDevice",
+        tokens: &[Token::Exact("gigadevice"), Token::Prefix("gd32")],
+    },
+    Vendor {
+        name: "Raspberry Pi",
+        tokens: &[Token::Prefix("rp2040"), Token::Prefix("rpi")],
+    },
+    Vendor {
+        name: "SiFive",
+        tokens: &[
+            Token::Exact("sifive"),
+            Token::Prefix("hifive"),
+            Token::Prefix("fe310"),
+            Token::Prefix("fu540"),
+        ],
+    },
+    Vendor {
+        name: "Renesas",
+        tokens: &[Token::Exact("renesas"), Token::Prefix("rcar")],
+    },
+    Vendor {
+        name: "Xilinx",
+        tokens: &[Token::Exact("xilinx"), Token::Prefix("zynq")],
+    },
+    Vendor {
+        name: "Analog Devices",
+        tokens: &[Token::Prefix("max32")],
+    },
+    Vendor {
+        name: "WCH",
+        tokens: &[Token::Prefix("ch32")],
+    },
+];
+
+impl Vendor {
+    fn matches(&self, word: &str) -> bool {
+        self.tokens.iter().any(|token| match token {
+            Token::Exact(keyword) => word == *keyword,
+            Token::Prefix(keyword) => word.starts_with(keyword),
+        })
+    }
+}
+
+/// Metadata of a config, fields are empty if they are not recognized
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Classes {
+    pub driver: Option<Driver>,
+    pub archs: Vec<Arch>,
+    pub vendor: Option<String>,
+}
+
+/// Scan a config and classify it
+///
+/// Configs which can't be read are classified by the name only.
+pub fn classify(config: &Config, scripts: &Path) -> Classes {
+    let facts = script::scan_file(Path::new(&config.path), scripts)
+        .map(|tree| tree.merged())
+        .unwrap_or_default();
+
+    Classes {
+        driver: driver(&config.name, &facts),
+        archs: archs(&facts),
+        vendor: vendor(&config.name, &facts),
+    }
+}
+
+fn driver(name: &str, facts: &ConfigFacts) -> Option<Driver> {
+    match facts.adapter_driver.as_deref()? {
+        "st-link" | "stlink" => Some(Driver::Stlink),
+        // ST-LINK configs of older versions use the high level adapter
+        "hla" if name.contains("stlink") || name.contains("st-link") => Some(Driver::Stlink),
+        "cmsis-dap" => Some(Driver::CmsisDap),
+        "jlink" => Some(Driver::Jlink),
+        "ftdi" => Some(Driver::Ftdi),
+        _ => None,
+    }
+}
+
+fn archs(facts: &ConfigFacts) -> Vec<Arch> {
+    let mut archs = Vec::new();
+    for arch in facts
+        .targets
+        .iter()
+        .filter_map(|target| Arch::from_target_type(&target.kind))
+    {
+        if !archs.contains(&arch) {
+            archs.push(arch);
+        }
+    }
+    archs
+}
+
+/// Guess the vendor by the chip name first, then by the config name
+fn vendor(name: &str, facts: &ConfigFacts) -> Option<String> {
+    let chipname = facts.chipname.as_deref().unwrap_or_default();
+
+    words(chipname)
+        .chain(words(name))
+        .find_map(|word| VENDORS.iter().find(|vendor| vendor.matches(&word)))
+        .map(|vendor| vendor.name.to_string())
+}
+
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify, Arch, Classes};
+    use crate::openocd::config::Config;
+    use crate::openocd::probe::Driver;
+    use crate::openocd::testing::TempDir;
+
+    fn classified(scripts: &TempDir, name: &str) -> Classes {
+        let config = Config {
+            name: name.rsplit('/').next().unwrap().into(),
+            path: scripts
+                .path()
+                .join(format!("{}.cfg", name))
+                .display()
+                .to_string(),
+            ..Config::default()
+        };
+        classify(&config, scripts.path())
+    }
+
+    #[test]
+    fn driver() {
+        let scripts = TempDir::new("classify-driver");
+        scripts.write("interface/stlink.cfg", "adapter driver st-link\n");
+        scripts.write(
+            "interface/stlink-v2.cfg",
+            "adapter driver hla\nhla_layout stlink\n",
+        );
+        scripts.write(
+            "interface/ti-icdi.cfg",
+            "adapter driver hla\nhla_layout ti-icdi\n",
+        );
+        scripts.write("interface/cmsis-dap.cfg", "interface cmsis-dap\n");
+        scripts.write(
+            "interface/ftdi/olimex-arm-usb-ocd-h.cfg",
+            "adapter driver ftdi\nftdi vid_pid 0x15ba 0x002b\n",
+        );
+        scripts.write("interface/jlink.cfg", "adapter driver jlink\n");
+        scripts.write("interface/sysfsgpio.cfg", "adapter driver sysfsgpio\n");
+
+        let driver = |name| classified(&scripts, name).driver;
+        assert_eq!(driver("interface/stlink"), Some(Driver::Stlink));
+        assert_eq!(driver("interface/stlink-v2"), Some(Driver::Stlink));
+        // Other high level adapters are not ST-LINK
+        assert_eq!(driver("interface/ti-icdi"), None);
+        assert_eq!(driver("interface/cmsis-dap"), Some(Driver::CmsisDap));
+        assert_eq!(
+            driver("interface/ftdi/olimex-arm-usb-ocd-h"),
+            Some(Driver::Ftdi)
+        );
+        assert_eq!(driver("interface/jlink"), Some(Driver::Jlink));
+        assert_eq!(driver("interface/sysfsgpio"), None);
+    }
+
+    #[test]
+    fn archs() {
+        let scripts = TempDir::new("classify-archs");
+        scripts.write(
+            "target/stm32h7x_dual_bank.cfg",
+            "set _CHIPNAME stm32h7x\n\
+             target create $_CHIPNAME.cpu0 cortex_m -dap $_CHIPNAME.dap\n\
+             target create $_CHIPNAME.cpu1 cortex_m -dap $_CHIPNAME.dap\n",
+        );
+        scripts.write(
+            "target/mixed.cfg",
+            "target create mixed.cpu0 aarch64 -dap mixed.dap\n\
+             target create mixed.cpu1 cortex_r4 -dap mixed.dap\n\
+             target create mixed.rv riscv -chain-position mixed.tap\n\
+             target create mixed.cti cti -dap mixed.dap\n",
+        );
+        scripts.write(
+            "board/board.cfg",
+            "source [find target/stm32h7x_dual_bank.cfg]\n",
+        );
+        scripts.write("interface/jlink.cfg", "adapter driver jlink\n");
+
+        let archs = |name| classified(&scripts, name).archs;
+        assert_eq!(archs("target/stm32h7x_dual_bank"), vec![Arch::CortexM]);
+        assert_eq!(
+            archs("target/mixed"),
+            vec![Arch::CortexA, Arch::CortexR, Arch::RiscV]
+        );
+        // Targets of included configs count
+        assert_eq!(archs("board/board"), vec![Arch::CortexM]);
+        assert!(archs("interface/jlink").is_empty());
+    }
+
+    #[test]
+    fn vendor() {
+        let scripts = TempDir::new("classify-vendor");
+        scripts.write("target/stm32f4x.cfg", "set _CHIPNAME stm32f4x\n");
+        scripts.write(
+            "target/generic.cfg",
+            "if { [info exists CHIPNAME] } {\n\
+             \x20   set _CHIPNAME $CHIPNAME\n\
+             } else {\n\
+             \x20   set _CHIPNAME generic\n\
+             }\n",
+        );
+        scripts.write(
+            "board/st_custom_nrf.cfg",
+            "set CHIPNAME nrf52\nsource [find target/generic.cfg]\n",
+        );
+        scripts.write(
+            "board/nordic_nrf52_dk.cfg",
+            "source [find target/generic.cfg]\n",
+        );
+        scripts.write("board/homemade.cfg", "source [find target/generic.cfg]\n");
+
+        let vendor = |name| classified(&scripts, name).vendor;
+        assert_eq!(
+            vendor("target/stm32f4x").as_deref(),
+            Some("STMicroelectronics")
+        );
+        // The chip name goes before the config name
+        assert_eq!(
+            vendor("board/st_custom_nrf").as_deref(),
+            Some("Nordic Semiconductor")
+        );
+        assert_eq!(
+            vendor("board/nordic_nrf52_dk").as_deref(),
+            Some("Nordic Semiconductor")
+        );
+        assert_eq!(vendor("board/homemade"), None);
+    }
+
+    #[test]
+    fn unreadable_config() {
+        let scripts = TempDir::new("classify-unreadable");
+
+        // Configs which can't be read are classified by the name only
+        assert_eq!(
+            classified(&scripts, "target/gd32vf103"),
+            Classes {
+                driver: None,
+                archs: Vec::new(),
+                vendor: Some("GigaDevice".into()),
+            }
+        );
+    }
+}
@@ -1,4 +1,6 @@
+use crate::openocd::classify::{self, Arch};
 use crate::openocd::paths::OpenocdPaths;
+use crate::openocd::probe::Driver;
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::path::Path;
@@ -12,6 +14,15 @@ struct ConfigFileName {
 pub struct Config {
     pub name: String,
     pub path: String,
+    /// Adapter driver of an interface config
+    #[serde(default)]
+    pub driver: Option<Driver>,
+    /// Architectures of cores declared by a target or board config
+    #[serde(default)]
+    pub archs: Vec<Arch>,
+    /// Chip vendor of a target or board config
+    #[serde(default)]
+    pub vendor: Option<String>,
 }
 
 impl ::std::default::Default for Config {
@@ -19,10 +30,30 @@ impl ::std::default::Default for Config {
         Self {
             name: "".into(),
             path: "".into(),
+            driver: None,
+            archs: Vec::new(),
+            vendor: None,
         }
     }
 }
 
+impl Config {
+    /// Make a config of a cfg file located in `configs_dir`
+    ///
+    /// The name is the path relative to `configs_dir` without the extension,
+    /// the same as of configs listed by `ConfigsSet`.
+    pub fn from_path(configs_dir: &Path, path: &Path) -> Option<Config> {
+        let relative_path = path.strip_prefix(configs_dir).ok()?;
+        let config_name = ConfigFileName::try_from(relative_path).ok()?;
+
+        Some(Config {
+            name: config_name.name,
+            path: path.display().to_string(),
+            ..Config::default()
+        })
+    }
+}
+
 impl TryFrom<&Path> for ConfigFileName {
     type Error = ();
 
@@ -55,7 +86,18 @@ impl TryFrom<&Path> for ConfigFileName {
     }
 }
 
-#[derive(Serialize)]
+/// Criteria to narrow lists of `ConfigsSet`, empty criteria match all configs
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigFilter {
+    pub driver: Option<Driver>,
+    pub arch: Option<Arch>,
+    pub vendor: Option<String>,
+    /// Part of the config name, case insensitive
+    pub text: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
 pub struct ConfigsSet {
     boards: Vec<Config>,
     interfaces: Vec<Config>,
@@ -65,13 +107,80 @@ pub struct ConfigsSet {
 impl ConfigsSet {
     pub fn new() -> Result<ConfigsSet, String> {
         let paths = OpenocdPaths::new();
-        paths.map(|paths| ConfigsSet {
-            boards: Self::extract_configs(paths.board.as_path()),
-            interfaces: Self::extract_configs(paths.interface.as_path()),
-            targets: Self::extract_configs(paths.target.as_path()),
+        paths.map(|paths| {
+            let mut set = ConfigsSet {
+                boards: Self::extract_configs(paths.board.as_path()),
+                interfaces: Self::extract_configs(paths.interface.as_path()),
+                targets: Self::extract_configs(paths.target.as_path()),
+            };
+            set.classify(&paths.scripts);
+            set
         })
     }
 
+    pub fn interfaces(&self) -> &[Config] {
+        &self.interfaces
+    }
+
+    /// Return configs which pass the filter
+    ///
+    /// The driver narrows interfaces only, architecture and vendor narrow
+    /// boards and targets only. The text is searched in names of all configs.
+    pub fn filter(self, filter: &ConfigFilter) -> ConfigsSet {
+        let text = filter.text.as_ref().map(|text| text.to_lowercase());
+        let has_text = |config: &Config| match &text {
+            Some(text) => config.name.to_lowercase().contains(text),
+            None => true,
+        };
+        let has_driver = |config: &Config| match filter.driver {
+            Some(driver) => config.driver == Some(driver),
+            None => true,
+        };
+        let has_chip = |config: &Config| {
+            let arch = match filter.arch {
+                Some(arch) => config.archs.contains(&arch),
+                None => true,
+            };
+            let vendor = match &filter.vendor {
+                Some(vendor) => config.vendor.as_ref() == Some(vendor),
+                None => true,
+            };
+            arch && vendor
+        };
+
+        ConfigsSet {
+            boards: self
+                .boards
+                .into_iter()
+                .filter(|config| has_text(config) && has_chip(config))
+                .collect(),
+            interfaces: self
+                .interfaces
+                .into_iter()
+                .filter(|config| has_text(config) && has_driver(config))
+                .collect(),
+            targets: self
+                .targets
+                .into_iter()
+                .filter(|config| has_text(config) && has_chip(config))
+                .collect(),
+        }
+    }
+
+    fn classify(&mut self, scripts: &Path) {
+        for config in self
+            .boards
+            .iter_mut()
+            .chain(self.interfaces.iter_mut())
+            .chain(self.targets.iter_mut())
+        {
+            let classes = classify::classify(config, scripts);
+            config.driver = classes.driver;
+            config.archs = classes.archs;
+            config.vendor = classes.vendor;
+        }
+    }
+
     fn extract_configs(path: &Path) -> Vec<Config> {
         Self::extract_configs_from(path).map_or(Vec::<Config>::new(), |cfgs| cfgs)
     }
@@ -96,6 +205,7 @@ impl ConfigsSet {
                             Some(Config {
                                 name: config_name.name,
                                 path: entry.path().display().to_string(),
+                                ..Config::default()
                             })
                         } else {
                             None
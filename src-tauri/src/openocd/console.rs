@@ -0,0 +1,386 @@
+use log::{error, info};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+
+use crate::error::ErrorMsg;
+use crate::openocd::{events::ConsoleOutput, session::SessionWindow};
+
+/// Default port of the OpenOCD telnet server
+pub const DEFAULT_PORT: u16 = 4444;
+
+/// Maximum count of lines stored in a console history
+const HISTORY_LIMIT: usize = 100;
+
+mod telnet {
+    pub const IAC: u8 = 255;
+    pub const DONT: u8 = 254;
+    pub const DO: u8 = 253;
+    pub const WONT: u8 = 252;
+    pub const WILL: u8 = 251;
+    pub const SB: u8 = 250;
+    pub const SE: u8 = 240;
+
+    pub const ECHO: u8 = 1;
+    pub const SUPPRESS_GO_AHEAD: u8 = 3;
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Data,
+    Command,
+    Option(u8),
+    Subnegotiation,
+    SubnegotiationCommand,
+}
+
+/// Filter of telnet negotiation sequences
+///
+/// OpenOCD starts every telnet session with a negotiation of echo and
+/// line mode. The filter strips these sequences from the received data and
+/// builds replies: remote echo and suppressed go-ahead are accepted, all the
+/// other options are refused.
+struct Negotiation {
+    state: State,
+}
+
+impl Negotiation {
+    fn new() -> Self {
+        Self { state: State::Data }
+    }
+
+    /// Split received bytes to console data and replies for the server
+    ///
+    /// Sequences split between two reads are handled by the inner state.
+    fn feed(&mut self, bytes: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        let mut data = Vec::with_capacity(bytes.len());
+        let mut replies = Vec::new();
+
+        for &byte in bytes {
+            self.state = match self.state {
+                State::Data if byte == telnet::IAC => State::Command,
+                State::Data => {
+                    data.push(byte);
+                    State::Data
+                }
+                State::Command => match byte {
+                    telnet::IAC => {
+                        data.push(byte);
+                        State::Data
+                    }
+                    telnet::WILL | telnet::WONT | telnet::DO | telnet::DONT => State::Option(byte),
+                    telnet::SB => State::Subnegotiation,
+                    _ => State::Data,
+                },
+                State::Option(command) => {
+                    if let Some(reply) = Self::reply(command, byte) {
+                        replies.extend_from_slice(&[telnet::IAC, reply, byte]);
+                    }
+                    State::Data
+                }
+                State::Subnegotiation if byte == telnet::IAC => State::SubnegotiationCommand,
+                State::Subnegotiation => State::Subnegotiation,
+                State::SubnegotiationCommand if byte == telnet::SE => State::Data,
+                State::SubnegotiationCommand => State::Subnegotiation,
+            }
+        }
+
+        (data, replies)
+    }
+
+    fn reply(command: u8, option: u8) -> Option<u8> {
+        let accepted = option == telnet::ECHO || option == telnet::SUPPRESS_GO_AHEAD;
+
+        match command {
+            telnet::WILL if accepted => Some(telnet::DO),
+            telnet::WILL => Some(telnet::DONT),
+            telnet::DO => Some(telnet::WONT),
+            _ => None,
+        }
+    }
+}
+
+/// Console session connected to the OpenOCD telnet server
+///
+/// Received output is emitted as `app://openocd/console` event of the OpenOCD
+/// session. Sent lines are stored in the history of the console session.
+pub struct Console {
+    stream: TcpStream,
+    history: Vec<String>,
+}
+
+impl Console {
+    /// Connect to the telnet server of OpenOCD started on localhost
+    ///
+    /// Start a thread which reads the console output until the connection
+    /// is closed.
+    pub fn open(port: u16, window: SessionWindow) -> Result<Console, ErrorMsg> {
+        let stream = TcpStream::connect(("127.0.0.1", port))
+            .map_err(|e| format!("Can't connect to OpenOCD telnet server: {}", e))?;
+
+        let reader = stream
+            .try_clone()
+            .map_err(|e| format!("Can't configure OpenOCD telnet connection: {}", e))?;
+
+        thread::spawn(move || Self::read_output(reader, window));
+
+        Ok(Console {
+            stream,
+            history: Vec::new(),
+        })
+    }
+
+    /// Send a line of input to OpenOCD
+    pub fn send(&mut self, line: String) -> Result<(), ErrorMsg> {
+        let line = line.trim_end().to_string();
+
+        self.stream
+            .write_all(format!("{}\n", line).as_bytes())
+            .map_err(|e| format!("Can't send to OpenOCD console: {}", e))?;
+
+        if !line.is_empty() && self.history.last() != Some(&line) {
+            self.history.push(line);
+            if self.history.len() > HISTORY_LIMIT {
+                self.history.remove(0);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return lines sent in this session, oldest first
+    pub fn history(&self) -> Vec<String> {
+        self.history.clone()
+    }
+
+    fn read_output(mut stream: TcpStream, window: SessionWindow) {
+        let mut negotiation = Negotiation::new();
+        let mut buffer = [0u8; 1024];
+
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => {
+                    let (data, replies) = negotiation.feed(&buffer[..size]);
+
+                    if !replies.is_empty() && stream.write_all(&replies).is_err() {
+                        break;
+                    }
+
+                    if !data.is_empty() {
+                        window.send(ConsoleOutput(String::from_utf8_lossy(&data).to_string()));
+                    }
+                }
+                Err(e) => {
+                    error!("OpenOCD console read failed: {}", e);
+                    break;
+                }
+            }
+        }
+
+        info!("OpenOCD console closed.");
+    }
+}
+
+impl Drop for Console {
+    fn drop(&mut self) {
+        self.stream.shutdown(Shutdown::Both).ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{telnet::*, Console, Negotiation, HISTORY_LIMIT};
+    use std::io::{BufRead, BufReader};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::{self, JoinHandle};
+
+    const LINEMODE: u8 = 34;
+    const TERMINAL_TYPE: u8 = 24;
+
+    /// Greeting negotiating echo, go-ahead and line mode before a prompt
+    const GREETING: &[u8] = &[
+        IAC,
+        WILL,
+        SUPPRESS_GO_AHEAD,
+        IAC,
+        WILL,
+        ECHO,
+        IAC,
+        DO,
+        LINEMODE,
+        b'>',
+        b' ',
+    ];
+
+    /// Feed the bytes in chunks split at the given boundaries
+    fn feed_split(bytes: &[u8], boundaries: &[usize]) -> (Vec<u8>, Vec<u8>) {
+        let mut negotiation = Negotiation::new();
+        let mut data = Vec::new();
+        let mut replies = Vec::new();
+        let mut start = 0;
+
+        for &end in boundaries.iter().chain([bytes.len()].iter()) {
+            let (chunk_data, chunk_replies) = negotiation.feed(&bytes[start..end]);
+            data.extend(chunk_data);
+            replies.extend(chunk_replies);
+            start = end;
+        }
+
+        (data, replies)
+    }
+
+    /// Check the bytes give the same result in one read and split anywhere
+    fn assert_feed(bytes: &[u8], data: &[u8], replies: &[u8]) {
+        assert_eq!(feed_split(bytes, &[]), (data.to_vec(), replies.to_vec()));
+
+        for first in 0..=bytes.len() {
+            assert_eq!(
+                feed_split(bytes, &[first]),
+                (data.to_vec(), replies.to_vec()),
+                "split at {}",
+                first
+            );
+            for second in first..=bytes.len() {
+                assert_eq!(
+                    feed_split(bytes, &[first, second]),
+                    (data.to_vec(), replies.to_vec()),
+                    "split at {} and {}",
+                    first,
+                    second
+                );
+            }
+        }
+
+        let bytewise: Vec<usize> = (1..bytes.len()).collect();
+        assert_eq!(
+            feed_split(bytes, &bytewise),
+            (data.to_vec(), replies.to_vec())
+        );
+    }
+
+    #[test]
+    fn greeting() {
+        assert_feed(
+            GREETING,
+            b"> ",
+            &[
+                IAC,
+                DO,
+                SUPPRESS_GO_AHEAD,
+                IAC,
+                DO,
+                ECHO,
+                IAC,
+                WONT,
+                LINEMODE,
+            ],
+        );
+    }
+
+    #[test]
+    fn refused_options() {
+        assert_feed(
+            &[
+                IAC, WILL, LINEMODE, IAC, DO, ECHO, IAC, WONT, ECHO, IAC, DONT, ECHO,
+            ],
+            &[],
+            &[IAC, DONT, LINEMODE, IAC, WONT, ECHO],
+        );
+    }
+
+    #[test]
+    fn subnegotiation() {
+        // Escaped IAC inside of the subnegotiation doesn't end it
+        assert_feed(
+            &[
+                b'a',
+                IAC,
+                SB,
+                TERMINAL_TYPE,
+                1,
+                IAC,
+                IAC,
+                b'x',
+                IAC,
+                SE,
+                b'b',
+            ],
+            b"ab",
+            &[],
+        );
+    }
+
+    #[test]
+    fn escaped_iac() {
+        assert_feed(
+            &[b'a', IAC, IAC, b'b', IAC, IAC],
+            &[b'a', IAC, b'b', IAC],
+            &[],
+        );
+    }
+
+    #[test]
+    fn other_commands() {
+        // No operation and go-ahead are dropped without a reply
+        assert_feed(&[b'a', IAC, 241, b'b', IAC, 249], b"ab", &[]);
+    }
+
+    /// Fake telnet server accepting one connection, returns the lines it got
+    fn serve() -> (u16, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            BufReader::new(stream).lines().map(Result::unwrap).collect()
+        });
+
+        (port, server)
+    }
+
+    fn console(port: u16) -> Console {
+        Console {
+            stream: TcpStream::connect(("127.0.0.1", port)).unwrap(),
+            history: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn history() {
+        let (port, server) = serve();
+        let mut console = console(port);
+
+        for line in ["reset halt  ", "", "reg pc", "reg pc", "reset halt"] {
+            console.send(line.into()).unwrap();
+        }
+        let history = console.history();
+        drop(console);
+
+        assert_eq!(history, vec!["reset halt", "reg pc", "reset halt"]);
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["reset halt", "", "reg pc", "reg pc", "reset halt"]
+        );
+    }
+
+    #[test]
+    fn history_limit() {
+        let (port, server) = serve();
+        let mut console = console(port);
+
+        for index in 0..HISTORY_LIMIT + 5 {
+            console.send(format!("mdw {}", index)).unwrap();
+        }
+        let history = console.history();
+        drop(console);
+
+        assert_eq!(history.len(), HISTORY_LIMIT);
+        assert_eq!(history.first().unwrap(), "mdw 5");
+        assert_eq!(
+            history.last().unwrap(),
+            &format!("mdw {}", HISTORY_LIMIT + 4)
+        );
+        assert_eq!(server.join().unwrap().len(), HISTORY_LIMIT + 5);
+    }
+}
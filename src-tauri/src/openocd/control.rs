@@ -0,0 +1,76 @@
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::error::ErrorMsg;
+use crate::openocd::{events::TargetState, rpc::TclClient, target::read_states};
+
+/// State of the target after `reset`
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResetMode {
+    /// Let the target run its firmware
+    Run,
+    /// Halt the target at the reset vector
+    Halt,
+    /// Halt the target and run `reset-init` scripts, e.g. clock setup
+    Init,
+}
+
+impl ResetMode {
+    fn name(self) -> &'static str {
+        match self {
+            ResetMode::Run => "run",
+            ResetMode::Halt => "halt",
+            ResetMode::Init => "init",
+        }
+    }
+}
+
+/// Result of a run control command
+#[derive(Clone, Debug, Serialize)]
+pub struct ControlResult {
+    /// Output of the OpenOCD command, usually empty
+    pub output: String,
+    /// Run states of targets after the command
+    pub targets: Vec<TargetState>,
+}
+
+/// Halt the current target and wait until it is halted
+pub fn halt(tcl: &mut TclClient) -> Result<ControlResult, ErrorMsg> {
+    run(tcl, "halt".to_string())
+}
+
+/// Resume the current target at the current PC or at `address`
+pub fn resume(tcl: &mut TclClient, address: Option<u64>) -> Result<ControlResult, ErrorMsg> {
+    run(tcl, with_address("resume", address))
+}
+
+/// Execute a single instruction of the halted target
+pub fn step(tcl: &mut TclClient, address: Option<u64>) -> Result<ControlResult, ErrorMsg> {
+    run(tcl, with_address("step", address))
+}
+
+/// Reset all targets
+pub fn reset(tcl: &mut TclClient, mode: ResetMode) -> Result<ControlResult, ErrorMsg> {
+    run(tcl, format!("reset {}", mode.name()))
+}
+
+fn with_address(command: &str, address: Option<u64>) -> String {
+    match address {
+        Some(address) => format!("{} {:#x}", command, address),
+        None => command.to_string(),
+    }
+}
+
+fn run(tcl: &mut TclClient, command: String) -> Result<ControlResult, ErrorMsg> {
+    info!("Target control: {}", command);
+
+    let output = tcl
+        .execute_checked(&command)
+        .map_err(|e| format!("\"{}\" failed: {}", command, e.message.trim()))?;
+
+    Ok(ControlResult {
+        output: output.trim().to_string(),
+        targets: read_states(tcl)?,
+    })
+}
@@ -1,12 +1,170 @@
+use serde::Serialize;
+
 use crate::api::TauriEvent;
 
-#[derive(Clone, Copy, serde_repr::Serialize_repr)]
+#[derive(Clone, Copy, PartialEq, serde_repr::Serialize_repr)]
 #[repr(u8)]
-pub enum Event {
+pub enum Kind {
     Start = 0,
     Stop = 1,
 }
 
+/// Who has initiated the stop of OpenOCD
+#[derive(Clone, Copy, PartialEq, serde_repr::Serialize_repr)]
+#[repr(u8)]
+pub enum Initiator {
+    /// Process was killed by user request
+    User = 0,
+    /// Process has exited by itself, e.g. on a bad config or a missing probe
+    Process = 1,
+}
+
+/// Details of the finished OpenOCD process
+#[derive(Clone, Serialize)]
+pub struct StopReport {
+    /// Exit code if process has exited normally
+    pub exit_code: Option<i32>,
+    /// Number of signal if process was terminated by a signal
+    pub signal: Option<i32>,
+    pub initiator: Initiator,
+    pub duration_ms: u64,
+    /// Last error lines OpenOCD has written to stderr
+    pub errors: Vec<String>,
+}
+
+/// Content of `app://openocd/event`
+///
+/// Stop event carries a report if the process was started.
+#[derive(Clone, Serialize)]
+pub struct Event {
+    pub kind: Kind,
+    #[serde(flatten)]
+    pub report: Option<StopReport>,
+}
+
+impl Event {
+    pub fn start() -> Self {
+        Self {
+            kind: Kind::Start,
+            report: None,
+        }
+    }
+
+    pub fn stop(report: Option<StopReport>) -> Self {
+        Self {
+            kind: Kind::Stop,
+            report,
+        }
+    }
+}
+
+/// Step of the OpenOCD shutdown sequence
+#[derive(Clone, Copy, Debug, serde_repr::Serialize_repr)]
+#[repr(u8)]
+pub enum ShutdownStep {
+    /// `shutdown` command is sent over the TCL connection
+    Command = 0,
+    /// SIGTERM is sent to the process group
+    Terminate = 1,
+    /// Process group is killed
+    Kill = 2,
+}
+
+/// Content of `app://openocd/shutdown`, emitted on every shutdown step
+#[derive(Clone, Serialize)]
+pub struct ShutdownProgress {
+    pub step: ShutdownStep,
+    /// Time given to the step before the next one is started
+    pub timeout_ms: u64,
+}
+
+/// Phase of firmware programming
+#[derive(Clone, Copy, Debug, PartialEq, serde_repr::Serialize_repr)]
+#[repr(u8)]
+pub enum FlashPhase {
+    /// Flash is erased and written by a single OpenOCD command
+    Erase = 0,
+    Write = 1,
+    Verify = 2,
+    Reset = 3,
+    Done = 4,
+    Failed = 5,
+}
+
+/// Content of `app://openocd/flash`, emitted on every programming phase
+///
+/// OpenOCD reports programming at phase boundaries only, so the percentage
+/// advances when a phase finishes. It is the share of bytes written and
+/// verified, the frontend shows the phase besides it.
+#[derive(Clone, Serialize)]
+pub struct FlashProgress {
+    pub phase: FlashPhase,
+    pub percent: u8,
+    /// Bytes written or verified by the phase
+    pub bytes: Option<u64>,
+    /// Throughput of the phase in KiB/s
+    pub kib_per_sec: Option<f64>,
+    pub elapsed_ms: u64,
+    /// Total time of programming, set for the final event only
+    pub total_ms: Option<u64>,
+}
+
+/// Run state of a target as reported by `$target curstate`
+#[derive(Clone, Copy, Debug, PartialEq, serde_repr::Serialize_repr)]
+#[repr(u8)]
+pub enum RunState {
+    Unknown = 0,
+    Running = 1,
+    Halted = 2,
+    Reset = 3,
+    /// Target runs code loaded by OpenOCD, e.g. a flash algorithm
+    DebugRunning = 4,
+}
+
+impl RunState {
+    pub fn parse(state: &str) -> Self {
+        match state.trim() {
+            "running" => RunState::Running,
+            "halted" => RunState::Halted,
+            "reset" => RunState::Reset,
+            "debug-running" => RunState::DebugRunning,
+            _ => RunState::Unknown,
+        }
+    }
+}
+
+/// Content of `app://openocd/target`, emitted when a target state changes
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TargetState {
+    pub target: String,
+    pub state: RunState,
+    /// Program counter, read only while the target is halted
+    pub pc: Option<u64>,
+}
+
+/// Content of `app://openocd/rtt`, text received from an RTT up-channel
+#[derive(Clone, Serialize)]
+pub struct RttOutput {
+    pub channel: u32,
+    pub data: String,
+}
+
+/// Content of `app://openocd/swo`, text written to an ITM stimulus port
+#[derive(Clone, Serialize)]
+pub struct SwoOutput {
+    pub port: u8,
+    pub data: String,
+}
+
+/// Content of `app://openocd/semihosting`, text written by the target
+/// through semihosting
+#[derive(Clone, Serialize)]
+pub struct SemihostingOutput(pub String);
+
+/// Output of the OpenOCD telnet console
+#[derive(Clone, Serialize)]
+pub struct ConsoleOutput(pub String);
+
 impl TauriEvent for String {
     fn topic(&self) -> &'static str {
         "app://openocd/output"
@@ -18,3 +176,45 @@ impl TauriEvent for Event {
         "app://openocd/event"
     }
 }
+
+impl TauriEvent for ConsoleOutput {
+    fn topic(&self) -> &'static str {
+        "app://openocd/console"
+    }
+}
+
+impl TauriEvent for ShutdownProgress {
+    fn topic(&self) -> &'static str {
+        "app://openocd/shutdown"
+    }
+}
+
+impl TauriEvent for FlashProgress {
+    fn topic(&self) -> &'static str {
+        "app://openocd/flash"
+    }
+}
+
+impl TauriEvent for RttOutput {
+    fn topic(&self) -> &'static str {
+        "app://openocd/rtt"
+    }
+}
+
+impl TauriEvent for SwoOutput {
+    fn topic(&self) -> &'static str {
+        "app://openocd/swo"
+    }
+}
+
+impl TauriEvent for SemihostingOutput {
+    fn topic(&self) -> &'static str {
+        "app://openocd/semihosting"
+    }
+}
+
+impl TauriEvent for TargetState {
+    fn topic(&self) -> &'static str {
+        "app://openocd/target"
+    }
+}
@@ -0,0 +1,435 @@
+use lazy_static::lazy_static;
+use log::info;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::error::ErrorMsg;
+use crate::openocd;
+use crate::openocd::config::Config;
+use crate::openocd::events::{FlashPhase, FlashProgress};
+use crate::openocd::log::{Level, Record};
+use crate::openocd::proc::LaunchOptions;
+use crate::openocd::rpc::TclClient;
+use crate::openocd::session::SessionWindow;
+
+/// Time to wait for `program` over the TCL connection of a running session
+const PROGRAM_TIMEOUT: Duration = Duration::from_secs(300);
+
+lazy_static! {
+    static ref STARTED: Regex = Regex::new(r"\*\* Programming Started \*\*").unwrap();
+    static ref WROTE: Regex =
+        Regex::new(r"^wrote (\d+) bytes from file .* in [\d.]+s \(([\d.]+) KiB/s\)").unwrap();
+    static ref VERIFY_STARTED: Regex = Regex::new(r"\*\* Verify Started \*\*").unwrap();
+    static ref VERIFIED: Regex =
+        Regex::new(r"^verified (\d+) bytes in [\d.]+s \(([\d.]+) KiB/s\)").unwrap();
+    static ref RESETTING: Regex = Regex::new(r"\*\* Resetting Target \*\*").unwrap();
+}
+
+/// Tracker of programming phases in OpenOCD output
+///
+/// Phases are recognized by the messages of OpenOCD `program` script and
+/// by the summaries of `flash write_image` and `verify_image`. OpenOCD
+/// doesn't report progress within a phase, so the percentage advances when
+/// a phase finishes: it is the share of bytes written and verified of all
+/// bytes to write and verify. The size of the image is taken from the
+/// `wrote N bytes` summary if it is unknown.
+#[derive(Default)]
+pub struct ProgressTracker {
+    started_at: Option<Instant>,
+    /// Bytes of the image to write, if known before programming
+    image_size: Option<u64>,
+    verify: bool,
+    written: u64,
+    percent: u8,
+}
+
+/// Tracker shared by the output reader of a session and `program_in_session`
+pub type SharedTracker = Arc<Mutex<ProgressTracker>>;
+
+impl ProgressTracker {
+    pub fn new(image_size: Option<u64>, verify: bool) -> Self {
+        ProgressTracker {
+            image_size,
+            verify,
+            ..ProgressTracker::default()
+        }
+    }
+
+    /// Return progress if the record starts or finishes a phase
+    pub fn track(&mut self, record: &Record) -> Option<FlashProgress> {
+        let message = record.message.as_str();
+
+        let (phase, caps) = if STARTED.is_match(message) {
+            self.started_at = Some(Instant::now());
+            self.written = 0;
+            self.percent = 0;
+            (FlashPhase::Erase, None)
+        } else if let Some(caps) = WROTE.captures(message) {
+            self.written = caps[1].parse().unwrap_or_default();
+            self.advance(self.written, 0);
+            (FlashPhase::Write, Some(caps))
+        } else if VERIFY_STARTED.is_match(message) {
+            // Verification of a session `program` is not known in advance
+            self.verify = true;
+            (FlashPhase::Verify, None)
+        } else if let Some(caps) = VERIFIED.captures(message) {
+            let verified = caps[1].parse().unwrap_or_default();
+            self.advance(self.written, verified);
+            (FlashPhase::Verify, Some(caps))
+        } else if RESETTING.is_match(message) {
+            (FlashPhase::Reset, None)
+        } else {
+            return None;
+        };
+
+        Some(FlashProgress {
+            phase,
+            percent: self.percent,
+            bytes: caps.as_ref().and_then(|caps| caps[1].parse().ok()),
+            kib_per_sec: caps.as_ref().and_then(|caps| caps[2].parse().ok()),
+            elapsed_ms: self.elapsed_ms(),
+            total_ms: None,
+        })
+    }
+
+    /// Return the final progress with the total time of programming
+    ///
+    /// `started_at` is used if the start of programming was not tracked.
+    pub fn finish(&self, success: bool, started_at: Instant) -> FlashProgress {
+        let total_ms = self
+            .started_at
+            .map_or(started_at.elapsed(), |at| at.elapsed())
+            .as_millis() as u64;
+
+        FlashProgress {
+            phase: if success {
+                FlashPhase::Done
+            } else {
+                FlashPhase::Failed
+            },
+            percent: if success { 100 } else { self.percent },
+            bytes: None,
+            kib_per_sec: None,
+            elapsed_ms: total_ms,
+            total_ms: Some(total_ms),
+        }
+    }
+
+    /// Update the percentage by bytes written and verified
+    ///
+    /// The percentage never goes back and reaches 100 by `finish` only.
+    fn advance(&mut self, written: u64, verified: u64) {
+        let size = self.image_size.unwrap_or(written).max(1);
+        let total = if self.verify { size * 2 } else { size };
+        let done = written.min(size) + verified.min(size);
+
+        let percent = (done * 100 / total).min(99) as u8;
+        self.percent = self.percent.max(percent);
+    }
+
+    fn elapsed_ms(&self) -> u64 {
+        self.started_at
+            .map_or(0, |at| at.elapsed().as_millis() as u64)
+    }
+}
+
+/// Format of a firmware image, detected by the file extension
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    Elf,
+    Hex,
+    Bin,
+}
+
+impl ImageFormat {
+    fn detect(path: &Path) -> Result<ImageFormat, ErrorMsg> {
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+
+        match extension.as_str() {
+            "elf" | "axf" | "out" => Ok(ImageFormat::Elf),
+            "hex" | "ihex" => Ok(ImageFormat::Hex),
+            "bin" => Ok(ImageFormat::Bin),
+            _ => Err(format!(
+                "Unknown format of image \"{}\": ELF, HEX or BIN is expected!",
+                path.display()
+            )
+            .into()),
+        }
+    }
+}
+
+/// Firmware image to program and `program` options
+#[derive(Clone, Debug, Deserialize)]
+pub struct ProgramRequest {
+    /// Path to an ELF, HEX or BIN image
+    pub path: String,
+    /// Base address of a BIN image, ignored for other formats
+    pub address: Option<u64>,
+    #[serde(default)]
+    pub verify: bool,
+    #[serde(default)]
+    pub reset: bool,
+    /// Shut down OpenOCD after programming; a one-shot run always exits
+    #[serde(default)]
+    pub exit: bool,
+}
+
+impl ProgramRequest {
+    /// Check the image and build the `program` command
+    fn command(&self, exit: bool) -> Result<(ImageFormat, String), ErrorMsg> {
+        let path = Path::new(&self.path);
+
+        if self.path.contains(&['{', '}'][..]) {
+            return Err("Bad image path: braces are not allowed!".into());
+        }
+        if !path.is_file() {
+            return Err(format!("Image \"{}\" not found!", self.path).into());
+        }
+
+        let format = ImageFormat::detect(path)?;
+
+        let mut command = format!("program {{{}}}", self.path);
+        if self.verify {
+            command.push_str(" verify");
+        }
+        if self.reset {
+            command.push_str(" reset");
+        }
+        if exit {
+            command.push_str(" exit");
+        }
+        if format == ImageFormat::Bin {
+            let address = self
+                .address
+                .ok_or_else(|| ErrorMsg::from("Base address is required for BIN image!"))?;
+            command.push_str(&format!(" {:#x}", address));
+        }
+
+        Ok((format, command))
+    }
+
+    /// Return count of bytes OpenOCD writes for the image, if it is known
+    ///
+    /// Data of a BIN image is the whole file, data of a HEX image is summed
+    /// by its data records. An ELF file contains debug info besides the
+    /// loaded data, so its size is not known until OpenOCD reports it.
+    fn image_size(&self, format: ImageFormat) -> Option<u64> {
+        match format {
+            ImageFormat::Bin => fs::metadata(&self.path).ok().map(|meta| meta.len()),
+            ImageFormat::Hex => {
+                let content = fs::read_to_string(&self.path).ok()?;
+                let size = content
+                    .lines()
+                    .filter(|line| line.get(7..9) == Some("00"))
+                    .filter_map(|line| u64::from_str_radix(line.get(1..3)?, 16).ok())
+                    .sum();
+                Some(size)
+            }
+            ImageFormat::Elf => None,
+        }
+    }
+}
+
+/// Result of programming returned to the frontend
+#[derive(Clone, Serialize)]
+pub struct ProgramResult {
+    pub success: bool,
+    pub format: ImageFormat,
+    /// Image was verified after programming
+    pub verified: bool,
+    pub duration_ms: u64,
+    pub message: String,
+    /// Error lines reported by OpenOCD
+    pub errors: Vec<String>,
+}
+
+impl ProgramResult {
+    fn new(request: &ProgramRequest, format: ImageFormat, started_at: Instant) -> Self {
+        ProgramResult {
+            success: true,
+            format,
+            verified: request.verify,
+            duration_ms: started_at.elapsed().as_millis() as u64,
+            message: "Programmed!".into(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn failed(mut self, errors: Vec<String>) -> Self {
+        self.success = false;
+        self.verified = false;
+        self.message = errors
+            .last()
+            .cloned()
+            .unwrap_or_else(|| "Programming failed!".into());
+        self.errors = errors;
+        self
+    }
+}
+
+/// Program an image with a separate OpenOCD process started with `configs`
+///
+/// OpenOCD is started without servers and exits after programming.
+/// Progress is emitted as `app://openocd/flash` event.
+/// Return error only if the request is bad or OpenOCD was not started.
+pub fn program_once(
+    configs: &[Config],
+    options: &LaunchOptions,
+    request: &ProgramRequest,
+    window: &SessionWindow,
+) -> Result<ProgramResult, ErrorMsg> {
+    options.validate()?;
+    let (format, command) = request.command(true)?;
+    info!("Program with one-shot OpenOCD: {}", command);
+
+    let started_at = Instant::now();
+    let mut proc = openocd::proc::run_once(configs, options, vec![command])
+        .ok_or_else(|| ErrorMsg::from("OpenOCD was not started!"))?;
+
+    let mut tracker = ProgressTracker::new(request.image_size(format), request.verify);
+    let stderr = proc.inner().stderr.take().unwrap();
+    let errors: Vec<String> = BufReader::new(stderr)
+        .lines()
+        .map_while(Result::ok)
+        .filter_map(|line| {
+            info!("-- [program] {}", line);
+            let record = Record::parse(&line);
+            if let Some(progress) = tracker.track(&record) {
+                window.send(progress);
+            }
+            if record.level == Level::Error {
+                Some(record.message)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    let status = proc
+        .wait()
+        .map_err(|e| format!("OpenOCD exit status is unknown: {}", e))?;
+
+    window.send(tracker.finish(status.success(), started_at));
+
+    let result = ProgramResult::new(request, format, started_at);
+    if status.success() {
+        Ok(result)
+    } else {
+        Ok(result.failed(errors))
+    }
+}
+
+/// Program an image with OpenOCD of a running session
+///
+/// `program` is run over the TCL connection, so OpenOCD keeps running
+/// unless `exit` is requested. Programming errors are returned as a failed
+/// result. Progress is tracked by the output reader of the session with
+/// `tracker`, only the final `app://openocd/flash` event is emitted here.
+pub fn program_in_session(
+    tcl_port: u16,
+    tracker: &SharedTracker,
+    request: &ProgramRequest,
+    window: &SessionWindow,
+) -> Result<ProgramResult, ErrorMsg> {
+    let (format, command) = request.command(false)?;
+    info!("Program with running OpenOCD: {}", command);
+
+    *tracker.lock().unwrap() = ProgressTracker::new(request.image_size(format), request.verify);
+
+    let started_at = Instant::now();
+    let response = TclClient::connect(tcl_port).and_then(|mut tcl| {
+        tcl.set_read_timeout(PROGRAM_TIMEOUT)?;
+        tcl.execute_checked(&command)
+    });
+
+    let progress = {
+        let mut tracker = tracker.lock().unwrap();
+        let progress = tracker.finish(response.is_ok(), started_at);
+        // Commands sent from the console are tracked without the request
+        *tracker = ProgressTracker::default();
+        progress
+    };
+    window.send(progress);
+
+    let result = ProgramResult::new(request, format, started_at);
+    match response {
+        Ok(_) => Ok(result),
+        Err(e) => {
+            let message = e
+                .message
+                .trim_matches(|c: char| c == '*' || c.is_whitespace());
+            Ok(result.failed(vec![message.to_string()]))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProgressTracker;
+    use crate::openocd::events::FlashPhase;
+    use crate::openocd::log::Record;
+
+    /// Output of `program firmware.elf verify reset` of OpenOCD 0.11.0
+    const PROGRAM_OUTPUT: &str = "\
+** Programming Started **
+Info : device id = 0x10036413
+Info : flash size = 1024 kbytes
+Info : Padding image section 0 at 0x080001c4 with 12 bytes
+wrote 16384 bytes from file build/firmware.elf in 0.812345s (19.696 KiB/s)
+** Programming Finished **
+** Verify Started **
+verified 16372 bytes in 0.156221s (102.346 KiB/s)
+** Verified OK **
+** Resetting Target **
+";
+
+    fn track(tracker: &mut ProgressTracker) -> Vec<(FlashPhase, u8)> {
+        PROGRAM_OUTPUT
+            .lines()
+            .filter_map(|line| tracker.track(&Record::parse(line)))
+            .map(|progress| (progress.phase, progress.percent))
+            .collect()
+    }
+
+    #[test]
+    fn percent_by_bytes() {
+        let mut tracker = ProgressTracker::new(Some(16384), true);
+
+        assert_eq!(
+            track(&mut tracker),
+            vec![
+                (FlashPhase::Erase, 0),
+                (FlashPhase::Write, 50),
+                (FlashPhase::Verify, 50),
+                (FlashPhase::Verify, 99),
+                (FlashPhase::Reset, 99),
+            ]
+        );
+    }
+
+    #[test]
+    fn verification_not_requested() {
+        // `program` sent from the console, the image and options are unknown
+        let mut tracker = ProgressTracker::default();
+
+        assert_eq!(
+            track(&mut tracker),
+            vec![
+                (FlashPhase::Erase, 0),
+                (FlashPhase::Write, 99),
+                (FlashPhase::Verify, 99),
+                (FlashPhase::Verify, 99),
+                (FlashPhase::Reset, 99),
+            ]
+        );
+    }
+}
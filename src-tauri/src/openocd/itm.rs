@@ -0,0 +1,220 @@
+//! Decoder of the ARMv7-M ITM packet stream captured through SWO
+//!
+//! Packets may be split between reads, so an incomplete packet is kept by
+//! the decoder until the rest of it is received.
+
+/// Maximum count of payload bytes of timestamp and extension packets
+const MAX_CONTINUATION: usize = 6;
+
+/// Count of zero bytes which precede `0x80` in a synchronization packet
+const SYNC_ZEROS: usize = 5;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Packet {
+    Sync,
+    /// Some packets were lost because the ITM FIFO was full
+    Overflow,
+    /// Data written by software to a stimulus port
+    Instrumentation {
+        port: u8,
+        payload: Vec<u8>,
+    },
+    /// Data generated by DWT, e.g. PC samples or exception trace
+    Hardware {
+        discriminator: u8,
+        payload: Vec<u8>,
+    },
+    LocalTimestamp,
+    GlobalTimestamp,
+    Extension,
+    /// Header of a reserved packet type
+    Unknown(u8),
+}
+
+#[derive(Default)]
+pub struct Decoder {
+    pending: Vec<u8>,
+    /// Count of zero bytes in a row which can start a synchronization packet
+    zeros: usize,
+}
+
+impl Decoder {
+    /// Decode received bytes to packets
+    pub fn feed(&mut self, data: &[u8]) -> Vec<Packet> {
+        let mut pending = std::mem::take(&mut self.pending);
+        pending.extend_from_slice(data);
+
+        let mut packets = Vec::new();
+        let mut pos = 0;
+
+        while pos < pending.len() {
+            match self.decode(&pending[pos..]) {
+                Some((packet, size)) => {
+                    pos += size;
+                    if let Some(packet) = packet {
+                        packets.push(packet);
+                    }
+                }
+                None => break,
+            }
+        }
+
+        pending.drain(..pos);
+        self.pending = pending;
+        packets
+    }
+
+    /// Decode a packet at the start of `data`
+    ///
+    /// Return the packet with its size, `None` packet for bytes which are a
+    /// part of a synchronization packet, or `None` if the packet is
+    /// incomplete.
+    fn decode(&mut self, data: &[u8]) -> Option<(Option<Packet>, usize)> {
+        let header = data[0];
+
+        if header == 0x00 {
+            self.zeros += 1;
+            return Some((None, 1));
+        }
+        if header == 0x80 && self.zeros >= SYNC_ZEROS {
+            self.zeros = 0;
+            return Some((Some(Packet::Sync), 1));
+        }
+        self.zeros = 0;
+
+        let (packet, size) = if header & 0x03 != 0 {
+            // Source packet: 1, 2 or 4 bytes of payload
+            let size = [0, 1, 2, 4][(header & 0x03) as usize];
+            let payload = data.get(1..=size)?.to_vec();
+            let address = header >> 3;
+
+            let packet = if header & 0x04 == 0 {
+                Packet::Instrumentation {
+                    port: address,
+                    payload,
+                }
+            } else {
+                Packet::Hardware {
+                    discriminator: address,
+                    payload,
+                }
+            };
+            (packet, 1 + size)
+        } else if header == 0x70 {
+            (Packet::Overflow, 1)
+        } else if header & 0x0F == 0x00 {
+            (Packet::LocalTimestamp, Self::continued_size(data)?)
+        } else if header == 0x94 || header == 0xB4 {
+            (Packet::GlobalTimestamp, Self::continued_size(data)?)
+        } else if header & 0x0B == 0x08 {
+            (Packet::Extension, Self::continued_size(data)?)
+        } else {
+            (Packet::Unknown(header), 1)
+        };
+
+        Some((Some(packet), size))
+    }
+
+    /// Return size of a packet whose bytes have the continuation bit 7
+    fn continued_size(data: &[u8]) -> Option<usize> {
+        let mut size = 1;
+        let mut continued = data[0] & 0x80 != 0;
+
+        while continued && size <= MAX_CONTINUATION {
+            continued = data.get(size)? & 0x80 != 0;
+            size += 1;
+        }
+
+        Some(size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decoder, Packet};
+
+    /// ITM stream assembled by hand after the packet format of the ARMv7-M
+    /// manual, not captured from hardware. It has text of stimulus ports 0
+    /// and 1 mixed with an overflow, local and global timestamps and a PC
+    /// sample.
+    const STREAM: &[u8] = include_bytes!("samples/itm_synthetic.bin");
+
+    fn text(packets: &[Packet], port: u8) -> String {
+        let bytes: Vec<u8> = packets
+            .iter()
+            .filter_map(|packet| match packet {
+                Packet::Instrumentation { port: p, payload } if *p == port => Some(payload.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect();
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn decodes_stimulus_ports() {
+        let packets = Decoder::default().feed(STREAM);
+
+        assert_eq!(packets[0], Packet::Sync);
+        assert_eq!(text(&packets, 0), "Hello, ITM!\nBoot OK\n");
+        assert_eq!(text(&packets, 1), "tick 1\ntick 2\n");
+        assert!(packets.contains(&Packet::Overflow));
+        assert!(packets.contains(&Packet::GlobalTimestamp));
+        assert!(packets.contains(&Packet::Hardware {
+            discriminator: 2,
+            payload: vec![0x34, 0x12, 0x00, 0x08],
+        }));
+    }
+
+    #[test]
+    fn keeps_split_packets() {
+        let whole = Decoder::default().feed(STREAM);
+
+        for chunk_size in 1..8 {
+            let mut decoder = Decoder::default();
+            let split: Vec<Packet> = STREAM
+                .chunks(chunk_size)
+                .flat_map(|chunk| decoder.feed(chunk))
+                .collect();
+            assert_eq!(split, whole, "chunks of {} bytes", chunk_size);
+        }
+    }
+
+    #[test]
+    fn decodes_payload_sizes() {
+        let packets = Decoder::default().feed(&[0x01, b'a', 0x0A, b'b', b'c', 0x1B, 1, 2, 3, 4]);
+
+        assert_eq!(
+            packets,
+            [
+                Packet::Instrumentation {
+                    port: 0,
+                    payload: vec![b'a'],
+                },
+                Packet::Instrumentation {
+                    port: 1,
+                    payload: vec![b'b', b'c'],
+                },
+                Packet::Instrumentation {
+                    port: 3,
+                    payload: vec![1, 2, 3, 4],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn waits_for_incomplete_packet() {
+        let mut decoder = Decoder::default();
+
+        assert!(decoder.feed(&[0x03, b'a', b'b']).is_empty());
+        assert!(decoder.feed(b"c").is_empty());
+        assert_eq!(
+            decoder.feed(b"d"),
+            [Packet::Instrumentation {
+                port: 0,
+                payload: b"abcd".to_vec(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::api::TauriEvent;
+
+/// Level of a line of OpenOCD output
+#[derive(Clone, Copy, Debug, PartialEq, serde_repr::Serialize_repr)]
+#[repr(u8)]
+pub enum Level {
+    Debug = 0,
+    Info = 1,
+    Warn = 2,
+    Error = 3,
+    User = 4,
+    /// Line without a level prefix, e.g. banner or command output
+    Output = 5,
+}
+
+/// Fields extracted from a known OpenOCD message
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Kind {
+    Version {
+        version: String,
+    },
+    Listening {
+        service: String,
+        port: u16,
+    },
+    Probe {
+        description: String,
+        vid: String,
+        pid: String,
+    },
+    TargetVoltage {
+        volts: f32,
+    },
+    ClockSpeed {
+        khz: u32,
+    },
+    Processor {
+        core: String,
+    },
+    Breakpoints {
+        breakpoints: u32,
+        watchpoints: Option<u32>,
+    },
+    Other,
+}
+
+/// Parsed line of OpenOCD output
+///
+/// Emitted as `app://openocd/log` event for every line OpenOCD writes
+/// to stderr.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Record {
+    pub level: Level,
+    /// Name of a target or a subsystem which produced the message
+    pub source: Option<String>,
+    pub message: String,
+    #[serde(flatten)]
+    pub kind: Kind,
+}
+
+lazy_static! {
+    static ref LEVEL: Regex = Regex::new(r"^(Debug|Info |Warn |Error|User ): ?(.*)$").unwrap();
+    static ref SOURCE: Regex =
+        Regex::new(r"^(?:\[(?P<bracket>[\w.\-]+)\]|(?P<colon>[\w\-]+\.[\w.\-]+):) (?P<rest>.*)$")
+            .unwrap();
+    static ref VERSION: Regex = Regex::new(r"Open On-Chip Debugger (\S+)").unwrap();
+    static ref LISTENING: Regex =
+        Regex::new(r"^Listening on port (\d+) for (\w+) connections").unwrap();
+    static ref PROBE: Regex =
+        Regex::new(r"^(.+?) VID:PID ([0-9a-fA-F]{4}):([0-9a-fA-F]{4})").unwrap();
+    static ref VOLTAGE: Regex = Regex::new(r"^Target voltage: ([\d.]+)").unwrap();
+    static ref CLOCK: Regex = Regex::new(r"^clock speed (\d+) kHz").unwrap();
+    static ref PROCESSOR: Regex = Regex::new(r"^(.+?) (?:r\dp\d )?processor detected").unwrap();
+    static ref BREAKPOINTS: Regex =
+        Regex::new(r"^hardware has (\d+) breakpoints(?:, (\d+) watchpoints)?").unwrap();
+}
+
+impl Record {
+    /// Parse a single line of OpenOCD output
+    ///
+    /// Never fails: a line of unknown format is returned as `Kind::Other`
+    /// with the whole line as a message.
+    pub fn parse(line: &str) -> Record {
+        let line = line.trim_end();
+
+        let (level, text) = match LEVEL.captures(line) {
+            Some(caps) => (
+                Self::level(&caps[1]),
+                caps.get(2).map_or("", |m| m.as_str()),
+            ),
+            None => (Level::Output, line),
+        };
+
+        let (source, message) = match SOURCE.captures(text) {
+            Some(caps) => {
+                let source = caps.name("bracket").or_else(|| caps.name("colon"));
+                (
+                    source.map(|s| s.as_str().to_string()),
+                    caps.name("rest").map_or("", |m| m.as_str()),
+                )
+            }
+            None => (None, text),
+        };
+
+        Record {
+            level,
+            source,
+            message: message.to_string(),
+            kind: Self::kind(message),
+        }
+    }
+
+    fn level(prefix: &str) -> Level {
+        match prefix.trim_end() {
+            "Debug" => Level::Debug,
+            "Info" => Level::Info,
+            "Warn" => Level::Warn,
+            "Error" => Level::Error,
+            _ => Level::User,
+        }
+    }
+
+    fn kind(message: &str) -> Kind {
+        if let Some(caps) = VERSION.captures(message) {
+            Kind::Version {
+                version: caps[1].to_string(),
+            }
+        } else if let Some(caps) = LISTENING.captures(message) {
+            Kind::Listening {
+                service: caps[2].to_string(),
+                port: caps[1].parse().unwrap_or_default(),
+            }
+        } else if let Some(caps) = PROBE.captures(message) {
+            Kind::Probe {
+                description: caps[1].to_string(),
+                vid: caps[2].to_lowercase(),
+                pid: caps[3].to_lowercase(),
+            }
+        } else if let Some(caps) = VOLTAGE.captures(message) {
+            Kind::TargetVoltage {
+                volts: caps[1].parse().unwrap_or_default(),
+            }
+        } else if let Some(caps) = CLOCK.captures(message) {
+            Kind::ClockSpeed {
+                khz: caps[1].parse().unwrap_or_default(),
+            }
+        } else if let Some(caps) = PROCESSOR.captures(message) {
+            Kind::Processor {
+                core: caps[1].to_string(),
+            }
+        } else if let Some(caps) = BREAKPOINTS.captures(message) {
+            Kind::Breakpoints {
+                breakpoints: caps[1].parse().unwrap_or_default(),
+                watchpoints: caps.get(2).and_then(|m| m.as_str().parse().ok()),
+            }
+        } else {
+            Kind::Other
+        }
+    }
+}
+
+impl TauriEvent for Record {
+    fn topic(&self) -> &'static str {
+        "app://openocd/log"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Kind, Level, Record};
+
+    /// Output of OpenOCD 0.11.0 started with `-f board/stm32f4discovery.cfg`
+    const STLINK_SESSION: &str = "\
+Open On-Chip Debugger 0.11.0
+Licensed under GNU GPL v2
+For bug reports, read
+	http://openocd.org/doc/doxygen/bugs.html
+Info : The selected transport took over low-level target control. The results might differ compared to plain JTAG/SWD
+srst_only separate srst_nogate srst_open_drain connect_deassert_srst
+
+Info : Listening on port 6666 for tcl connections
+Info : Listening on port 4444 for telnet connections
+Info : clock speed 2000 kHz
+Info : STLINK V2J37S0 (API v2) VID:PID 0483:3748
+Info : Target voltage: 3.25
+Info : stm32f4x.cpu: hardware has 6 breakpoints, 4 watchpoints
+Info : starting gdb server for stm32f4x.cpu on 3333
+Info : Listening on port 3333 for gdb connections
+";
+
+    fn records(output: &str) -> Vec<Record> {
+        output.lines().map(Record::parse).collect()
+    }
+
+    #[test]
+    fn stlink_session() {
+        let records = records(STLINK_SESSION);
+
+        let kinds: Vec<&Kind> = records
+            .iter()
+            .filter(|record| record.kind != Kind::Other)
+            .map(|record| &record.kind)
+            .collect();
+
+        assert_eq!(
+            kinds,
+            vec![
+                &Kind::Version {
+                    version: "0.11.0".into()
+                },
+                &Kind::Listening {
+                    service: "tcl".into(),
+                    port: 6666
+                },
+                &Kind::Listening {
+                    service: "telnet".into(),
+                    port: 4444
+                },
+                &Kind::ClockSpeed { khz: 2000 },
+                &Kind::Probe {
+                    description: "STLINK V2J37S0 (API v2)".into(),
+                    vid: "0483".into(),
+                    pid: "3748".into()
+                },
+                &Kind::TargetVoltage { volts: 3.25 },
+                &Kind::Breakpoints {
+                    breakpoints: 6,
+                    watchpoints: Some(4)
+                },
+                &Kind::Listening {
+                    service: "gdb".into(),
+                    port: 3333
+                },
+            ]
+        );
+
+        // Banner and output of commands have no level
+        assert_eq!(records[1].level, Level::Output);
+        assert_eq!(records[1].message, "Licensed under GNU GPL v2");
+        assert_eq!(records[5].level, Level::Output);
+        assert_eq!(records[6].message, "");
+    }
+
+    #[test]
+    fn listening() {
+        let record = Record::parse("Info : Listening on port 3333 for gdb connections");
+
+        assert_eq!(record.level, Level::Info);
+        assert_eq!(record.source, None);
+        assert_eq!(record.message, "Listening on port 3333 for gdb connections");
+        assert_eq!(
+            record.kind,
+            Kind::Listening {
+                service: "gdb".into(),
+                port: 3333
+            }
+        );
+    }
+
+    #[test]
+    fn target_voltage() {
+        let record = Record::parse("Info : Target voltage: 3.25");
+
+        assert_eq!(record.level, Level::Info);
+        assert_eq!(record.source, None);
+        assert_eq!(record.kind, Kind::TargetVoltage { volts: 3.25 });
+
+        let record = Record::parse("Info : Target voltage: 3.242857\r\n");
+        assert_eq!(record.message, "Target voltage: 3.242857");
+        assert_eq!(record.kind, Kind::TargetVoltage { volts: 3.242857 });
+    }
+
+    #[test]
+    fn error() {
+        let record = Record::parse("Error: open failed");
+
+        assert_eq!(record.level, Level::Error);
+        assert_eq!(record.source, None);
+        assert_eq!(record.message, "open failed");
+        assert_eq!(record.kind, Kind::Other);
+    }
+
+    #[test]
+    fn levels() {
+        let level = |line| Record::parse(line).level;
+
+        assert_eq!(
+            level("Debug: 209 2 command.c:544 run_command(): command - init"),
+            Level::Debug
+        );
+        assert_eq!(
+            level("Warn : Interface already configured, ignoring"),
+            Level::Warn
+        );
+        assert_eq!(
+            level("User : 57 1 target.c:2874 handle_target(): Halt timed out, wake up GDB."),
+            Level::User
+        );
+        assert_eq!(level("Polling target stm32f1x.cpu failed"), Level::Output);
+    }
+
+    #[test]
+    fn breakpoints_of_target() {
+        let record =
+            Record::parse("Info : stm32f4x.cpu: hardware has 6 breakpoints, 4 watchpoints");
+
+        assert_eq!(record.level, Level::Info);
+        assert_eq!(record.source.as_deref(), Some("stm32f4x.cpu"));
+        assert_eq!(record.message, "hardware has 6 breakpoints, 4 watchpoints");
+        assert_eq!(
+            record.kind,
+            Kind::Breakpoints {
+                breakpoints: 6,
+                watchpoints: Some(4)
+            }
+        );
+
+        // RISC-V targets of OpenOCD 0.12 are named in brackets
+        let record = Record::parse("Info : [riscv.cpu] hardware has 8 breakpoints");
+        assert_eq!(record.source.as_deref(), Some("riscv.cpu"));
+        assert_eq!(
+            record.kind,
+            Kind::Breakpoints {
+                breakpoints: 8,
+                watchpoints: None
+            }
+        );
+    }
+
+    #[test]
+    fn processor() {
+        let record = Record::parse("Info : stm32f1x.cpu: Cortex-M3 r1p1 processor detected");
+
+        assert_eq!(record.source.as_deref(), Some("stm32f1x.cpu"));
+        assert_eq!(
+            record.kind,
+            Kind::Processor {
+                core: "Cortex-M3".into()
+            }
+        );
+
+        let record = Record::parse("Info : [stm32f4x.cpu] Cortex-M4 r0p1 processor detected");
+        assert_eq!(record.source.as_deref(), Some("stm32f4x.cpu"));
+        assert_eq!(
+            record.kind,
+            Kind::Processor {
+                core: "Cortex-M4".into()
+            }
+        );
+    }
+
+    #[test]
+    fn version_banners() {
+        let version = |line| match Record::parse(line).kind {
+            Kind::Version { version } => version,
+            kind => panic!("Not a version: {:?}", kind),
+        };
+
+        assert_eq!(version("Open On-Chip Debugger 0.11.0"), "0.11.0");
+        assert_eq!(
+            version("Open On-Chip Debugger 0.12.0+dev-01234-g5f7b2a1c6 (2023-05-16-12:00)"),
+            "0.12.0+dev-01234-g5f7b2a1c6"
+        );
+        assert_eq!(
+            version("xPack OpenOCD x86_64 Open On-Chip Debugger 0.11.0+dev (2021-10-16-21:15)"),
+            "0.11.0+dev"
+        );
+        assert_eq!(
+            Record::parse("Open On-Chip Debugger 0.11.0").level,
+            Level::Output
+        );
+    }
+
+    #[test]
+    fn probes_and_clock() {
+        let record = Record::parse("Info : CMSIS-DAP: Interface Initialised (SWD)");
+        assert_eq!(record.kind, Kind::Other);
+
+        let record = Record::parse("Info : J-Link V11 compiled Jun 20 2022 17:49:39");
+        assert_eq!(record.kind, Kind::Other);
+
+        let record = Record::parse("Info : STLINK V3J7M2 (API v3) VID:PID 0483:374E");
+        assert_eq!(
+            record.kind,
+            Kind::Probe {
+                description: "STLINK V3J7M2 (API v3)".into(),
+                vid: "0483".into(),
+                pid: "374e".into()
+            }
+        );
+
+        let record = Record::parse("Info : clock speed 1800 kHz");
+        assert_eq!(record.level, Level::Info);
+        assert_eq!(record.kind, Kind::ClockSpeed { khz: 1800 });
+    }
+}
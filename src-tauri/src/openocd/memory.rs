@@ -0,0 +1,386 @@
+use serde::Serialize;
+
+use crate::error::ErrorMsg;
+use crate::openocd::rpc::TclClient;
+
+/// Max count of bytes read or written by a single request
+pub const SIZE_LIMIT: u64 = 64 * 1024;
+
+/// Width of memory access in bits
+#[derive(
+    Clone, Copy, Debug, PartialEq, serde_repr::Serialize_repr, serde_repr::Deserialize_repr,
+)]
+#[repr(u8)]
+pub enum Width {
+    Byte = 8,
+    Half = 16,
+    Word = 32,
+    Double = 64,
+}
+
+impl Width {
+    pub fn from_bits(bits: u32) -> Option<Width> {
+        match bits {
+            8 => Some(Width::Byte),
+            16 => Some(Width::Half),
+            32 => Some(Width::Word),
+            64 => Some(Width::Double),
+            _ => None,
+        }
+    }
+
+    fn bytes(self) -> u64 {
+        self as u64 / 8
+    }
+
+    fn max_value(self) -> u64 {
+        match self {
+            Width::Double => u64::MAX,
+            _ => (1 << (self as u64)) - 1,
+        }
+    }
+
+    /// Suffix of `md*`/`mw*` commands of OpenOCD before 0.12
+    fn legacy_suffix(self) -> char {
+        match self {
+            Width::Byte => 'b',
+            Width::Half => 'h',
+            Width::Word => 'w',
+            Width::Double => 'd',
+        }
+    }
+}
+
+/// Values read from consecutive addresses of the target memory
+#[derive(Clone, Serialize)]
+pub struct MemoryBlock {
+    pub address: u64,
+    pub width: Width,
+    pub values: Vec<u64>,
+}
+
+/// Read `count` values of `width` starting at `address`
+///
+/// `read_memory` is used if OpenOCD has it (0.12+), otherwise the output
+/// of `mdb`/`mdh`/`mdw`/`mdd` is parsed.
+pub fn read(
+    tcl: &mut TclClient,
+    address: u64,
+    width: Width,
+    count: u64,
+) -> Result<MemoryBlock, ErrorMsg> {
+    check_range(address, width, count)?;
+
+    let native = has_read_memory(tcl)?;
+    read_with(tcl, native, address, width, count)
+}
+
+/// Check OpenOCD has `read_memory`, once for a series of `read_with`
+pub fn has_read_memory(tcl: &mut TclClient) -> Result<bool, ErrorMsg> {
+    tcl.has_command("read_memory")
+}
+
+/// Read like `read` with the result of `has_read_memory` known
+pub fn read_with(
+    tcl: &mut TclClient,
+    native: bool,
+    address: u64,
+    width: Width,
+    count: u64,
+) -> Result<MemoryBlock, ErrorMsg> {
+    check_range(address, width, count)?;
+
+    let values = tcl
+        .execute_checked(&read_command(native, address, width, count))
+        .and_then(|response| {
+            if native {
+                parse_values(response.split_whitespace())
+            } else {
+                parse_dump(&response)
+            }
+        })
+        .map_err(|e| read_error(address, e))?;
+
+    if values.len() as u64 != count {
+        return Err(read_error(
+            address,
+            format!("{} values expected, {} received", count, values.len()).into(),
+        ));
+    }
+
+    Ok(MemoryBlock {
+        address,
+        width,
+        values,
+    })
+}
+
+/// Write values of `width` to consecutive addresses starting at `address`
+///
+/// `write_memory` is used if OpenOCD has it (0.12+), otherwise a value is
+/// written by `mwb`/`mwh`/`mww`/`mwd` one at a time.
+pub fn write(
+    tcl: &mut TclClient,
+    address: u64,
+    width: Width,
+    values: &[u64],
+) -> Result<(), ErrorMsg> {
+    check_range(address, width, values.len() as u64)?;
+
+    if let Some(value) = values.iter().find(|value| **value > width.max_value()) {
+        return Err(format!("Value {:#x} doesn't fit into {} bits!", value, width as u8).into());
+    }
+
+    let native = tcl.has_command("write_memory")?;
+    let command = write_command(native, address, width, values);
+
+    tcl.execute_checked(&command)
+        .map(|_| ())
+        .map_err(|e| format!("Can't write memory at {:#x}: {}", address, e.message.trim()).into())
+}
+
+/// Make `read_memory` command of OpenOCD 0.12+ or `md*` of older ones
+fn read_command(native: bool, address: u64, width: Width, count: u64) -> String {
+    if native {
+        format!("read_memory {:#x} {} {}", address, width as u8, count)
+    } else {
+        format!("md{} {:#x} {}", width.legacy_suffix(), address, count)
+    }
+}
+
+/// Make `write_memory` command of OpenOCD 0.12+ or a `mw*` per value
+fn write_command(native: bool, address: u64, width: Width, values: &[u64]) -> String {
+    if native {
+        let values: Vec<String> = values.iter().map(|value| format!("{:#x}", value)).collect();
+        format!(
+            "write_memory {:#x} {} {{{}}}",
+            address,
+            width as u8,
+            values.join(" ")
+        )
+    } else {
+        values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                format!(
+                    "mw{} {:#x} {:#x}",
+                    width.legacy_suffix(),
+                    address + i as u64 * width.bytes(),
+                    value
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("; ")
+    }
+}
+
+fn check_range(address: u64, width: Width, count: u64) -> Result<(), ErrorMsg> {
+    if address & (width.bytes() - 1) != 0 {
+        return Err(format!(
+            "Address {:#x} is not aligned to {} bits!",
+            address, width as u8
+        )
+        .into());
+    }
+    if count == 0 {
+        return Err("Nothing to read or write: count is zero!".into());
+    }
+    let size = count.saturating_mul(width.bytes());
+    if size > SIZE_LIMIT {
+        return Err(format!("Memory range is limited to {} bytes!", SIZE_LIMIT).into());
+    }
+    if address.checked_add(size - 1).is_none() {
+        return Err("Memory range exceeds the address space!".into());
+    }
+    Ok(())
+}
+
+fn read_error(address: u64, error: ErrorMsg) -> ErrorMsg {
+    format!(
+        "Can't read memory at {:#x}: {}",
+        address,
+        error.message.trim()
+    )
+    .into()
+}
+
+fn parse_hex(value: &str) -> Result<u64, ErrorMsg> {
+    let digits = value.trim_start_matches("0x");
+    u64::from_str_radix(digits, 16).map_err(|_| format!("bad value \"{}\"", value).into())
+}
+
+fn parse_values<'a>(values: impl Iterator<Item = &'a str>) -> Result<Vec<u64>, ErrorMsg> {
+    values.map(parse_hex).collect()
+}
+
+/// Parse values from the output of `md*` commands
+///
+/// Every line looks like `0x20000000: 12345678 9abcdef0`, values are taken
+/// up to the first token which is not hexadecimal.
+fn parse_dump(dump: &str) -> Result<Vec<u64>, ErrorMsg> {
+    let mut values = Vec::new();
+
+    for line in dump.lines() {
+        let data = match line.split_once(':') {
+            Some((_, data)) => data,
+            None => continue,
+        };
+        let hex: Vec<&str> = data
+            .split_whitespace()
+            .take_while(|token| token.chars().all(|c| c.is_ascii_hexdigit()))
+            .collect();
+        values.extend(parse_values(hex.into_iter())?);
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_range, parse_dump, read_command, write_command, Width, SIZE_LIMIT};
+
+    #[test]
+    fn dumps() {
+        let cases: &[(&str, &[u64])] = &[
+            // `mdw 0x08000000 6` of OpenOCD 0.11
+            (
+                "0x08000000: 20005000 08000145 0800014d 0800014f 08000151 08000153 \n",
+                &[
+                    0x20005000, 0x08000145, 0x0800014d, 0x0800014f, 0x08000151, 0x08000153,
+                ],
+            ),
+            // `mdh` wraps lines
+            (
+                "0x20000000: 0001 0002 0003 0004 0005 0006 0007 0008 \n\
+                 0x20000010: 0009 000a \n",
+                &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+            ),
+            ("0x20000000: 12 34 \n", &[0x12, 0x34]),
+            ("0x20000000: 0123456789abcdef \n", &[0x0123456789abcdef]),
+            // Values are taken up to the first token which is not hexadecimal
+            (
+                "0x20000000: 1234abcd cafebabe .4..\n",
+                &[0x1234abcd, 0xcafebabe],
+            ),
+            // Lines without an address are not dumps
+            ("target not halted\n", &[]),
+            ("", &[]),
+        ];
+
+        for (dump, values) in cases {
+            assert_eq!(&parse_dump(dump).unwrap(), values, "{:?}", dump);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        let cases = [
+            (0x2000_0000, Width::Word, 4, Ok(())),
+            (0x2000_0001, Width::Byte, 1, Ok(())),
+            (0x2000_0002, Width::Half, 1, Ok(())),
+            (
+                0x2000_0002,
+                Width::Word,
+                1,
+                Err("Address 0x20000002 is not aligned to 32 bits!"),
+            ),
+            (
+                0x2000_0004,
+                Width::Double,
+                1,
+                Err("Address 0x20000004 is not aligned to 64 bits!"),
+            ),
+            (
+                0x2000_0000,
+                Width::Word,
+                0,
+                Err("Nothing to read or write: count is zero!"),
+            ),
+            (0x2000_0000, Width::Byte, SIZE_LIMIT, Ok(())),
+            (
+                0x2000_0000,
+                Width::Half,
+                SIZE_LIMIT / 2 + 1,
+                Err("Memory range is limited to 65536 bytes!"),
+            ),
+            (
+                0,
+                Width::Double,
+                u64::MAX,
+                Err("Memory range is limited to 65536 bytes!"),
+            ),
+            (u64::MAX - 7, Width::Double, 1, Ok(())),
+            (
+                u64::MAX - 3,
+                Width::Word,
+                2,
+                Err("Memory range exceeds the address space!"),
+            ),
+        ];
+
+        for (address, width, count, expected) in cases {
+            assert_eq!(
+                check_range(address, width, count).map_err(|e| e.message),
+                expected.map_err(String::from),
+                "{:#x} {:?} {}",
+                address,
+                width,
+                count
+            );
+        }
+    }
+
+    #[test]
+    fn read_commands() {
+        let cases = [
+            (true, Width::Word, "read_memory 0x20000000 32 4"),
+            (false, Width::Byte, "mdb 0x20000000 4"),
+            (false, Width::Half, "mdh 0x20000000 4"),
+            (false, Width::Word, "mdw 0x20000000 4"),
+            (false, Width::Double, "mdd 0x20000000 4"),
+        ];
+
+        for (native, width, command) in cases {
+            assert_eq!(read_command(native, 0x2000_0000, width, 4), command);
+        }
+    }
+
+    #[test]
+    fn write_commands() {
+        let cases = [
+            (
+                true,
+                Width::Word,
+                "write_memory 0x20000000 32 {0x1 0xdeadbeef}",
+            ),
+            (
+                false,
+                Width::Byte,
+                "mwb 0x20000000 0x1; mwb 0x20000001 0xdeadbeef",
+            ),
+            (
+                false,
+                Width::Half,
+                "mwh 0x20000000 0x1; mwh 0x20000002 0xdeadbeef",
+            ),
+            (
+                false,
+                Width::Word,
+                "mww 0x20000000 0x1; mww 0x20000004 0xdeadbeef",
+            ),
+            (
+                false,
+                Width::Double,
+                "mwd 0x20000000 0x1; mwd 0x20000008 0xdeadbeef",
+            ),
+        ];
+
+        for (native, width, command) in cases {
+            assert_eq!(
+                write_command(native, 0x2000_0000, width, &[1, 0xdead_beef]),
+                command
+            );
+        }
+    }
+}
@@ -1,4 +1,24 @@
 pub mod proc;
+pub mod banks;
+pub mod board;
+pub mod classify;
 pub mod config;
+pub mod console;
+pub mod control;
 pub mod events;
+pub mod flash;
+pub mod itm;
+pub mod log;
+pub mod memory;
+pub mod ports;
+pub mod probe;
+pub mod rpc;
+pub mod rtt;
+pub mod script;
+pub mod session;
+pub mod swo;
+pub mod target;
+#[cfg(test)]
+pub mod testing;
+pub mod version;
 mod paths;
@@ -11,6 +11,8 @@ use regex::Regex;
 use crate::openocd::proc::start_exec;
 
 pub struct OpenocdPaths {
+    /// Root of scripts searched by `find`
+    pub scripts: PathBuf,
     pub board: PathBuf,
     pub target: PathBuf,
     pub interface: PathBuf,
@@ -20,15 +22,21 @@ impl OpenocdPaths {
     pub fn new() -> Result<OpenocdPaths, String> {
         let paths = Self::root()
             .and_then(|root| Self::scripts(root.as_path()))
-            .map(|scripts| OpenocdPaths {
-                board: Self::board(scripts.as_path()),
-                target: Self::target(scripts.as_path()),
-                interface: Self::interface(scripts.as_path()),
-            });
+            .map(Self::with_scripts);
 
         paths.ok_or_else(|| "OpenOCD not found!".into())
     }
 
+    /// Make paths of config folders in the scripts folder
+    pub fn with_scripts(scripts: PathBuf) -> OpenocdPaths {
+        OpenocdPaths {
+            board: Self::board(scripts.as_path()),
+            target: Self::target(scripts.as_path()),
+            interface: Self::interface(scripts.as_path()),
+            scripts,
+        }
+    }
+
     fn board(script: &Path) -> PathBuf {
         script.join("board")
     }
@@ -0,0 +1,344 @@
+use serde::Serialize;
+use std::net::TcpListener;
+
+use crate::error::ErrorMsg;
+use crate::openocd;
+
+/// Default port of the OpenOCD GDB server
+pub const GDB_DEFAULT_PORT: u16 = 3333;
+
+/// Count of ports tried after a busy one while searching for a free port
+const SEARCH_RANGE: u16 = 100;
+
+/// Distance between ports of sessions
+///
+/// OpenOCD serves GDB of the second and next targets of a multi-core chip
+/// on the ports following the GDB port, so sessions leave room for them.
+const SESSION_STRIDE: u16 = 10;
+
+/// Ports of OpenOCD servers of a session
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct Ports {
+    pub gdb: u16,
+    pub telnet: u16,
+    pub tcl: u16,
+}
+
+/// Process which listens on a port
+#[derive(Clone, Serialize)]
+pub struct PortOwner {
+    pub pid: u32,
+    pub name: String,
+}
+
+/// Port of an OpenOCD server which is already in use
+#[derive(Clone, Serialize)]
+pub struct BusyPort {
+    pub service: &'static str,
+    pub port: u16,
+    pub owner: Option<PortOwner>,
+}
+
+impl Ports {
+    /// Return the n-th set of ports
+    ///
+    /// The first set is the OpenOCD defaults, every next set is shifted by
+    /// `SESSION_STRIDE`.
+    pub fn nth(index: u16) -> Self {
+        let shift = index * SESSION_STRIDE;
+        Self {
+            gdb: GDB_DEFAULT_PORT + shift,
+            telnet: openocd::console::DEFAULT_PORT + shift,
+            tcl: openocd::rpc::DEFAULT_PORT + shift,
+        }
+    }
+
+    /// Check any port of one set is in the range of ports of the other one
+    pub fn overlaps(&self, other: &Ports) -> bool {
+        let near = |a: u16, b: u16| {
+            let (low, high) = if a < b { (a, b) } else { (b, a) };
+            high - low < SESSION_STRIDE
+        };
+        self.services().iter().any(|&(_, port)| {
+            other
+                .services()
+                .iter()
+                .any(|&(_, other_port)| near(port, other_port))
+        })
+    }
+
+    /// Return ports which are already in use with their owners
+    pub fn busy(&self) -> Vec<BusyPort> {
+        self.services()
+            .iter()
+            .filter(|(_, port)| !is_free(*port))
+            .map(|&(service, port)| BusyPort {
+                service,
+                port,
+                owner: owner(port),
+            })
+            .collect()
+    }
+
+    /// Replace every busy port with the nearest free one
+    ///
+    /// Ports in the ranges of `reserved` sets are skipped, so ports of other
+    /// sessions are left to them even while they are stopped. Return error
+    /// if no free port was found near a busy one.
+    pub fn reassign(&self, reserved: &[Ports]) -> Result<Ports, ErrorMsg> {
+        self.reassign_with(reserved, is_free)
+    }
+
+    fn reassign_with<F>(&self, reserved: &[Ports], is_free: F) -> Result<Ports, ErrorMsg>
+    where
+        F: Fn(u16) -> bool,
+    {
+        let mut taken: Vec<u16> = Vec::new();
+        let mut pick = |service: &str, port: u16| -> Result<u16, ErrorMsg> {
+            let free = (port..port.saturating_add(SEARCH_RANGE))
+                .find(|port| {
+                    !taken.contains(port)
+                        && !reserved.iter().any(|ports| ports.reserves(*port))
+                        && is_free(*port)
+                })
+                .ok_or_else(|| ErrorMsg::from(format!("No free port for {} server!", service)))?;
+            taken.push(free);
+            Ok(free)
+        };
+
+        Ok(Ports {
+            gdb: pick("gdb", self.gdb)?,
+            telnet: pick("telnet", self.telnet)?,
+            tcl: pick("tcl", self.tcl)?,
+        })
+    }
+
+    /// Check the port is in the range of a port of the set
+    fn reserves(&self, port: u16) -> bool {
+        self.services()
+            .iter()
+            .any(|&(_, start)| port >= start && port - start < SESSION_STRIDE)
+    }
+
+    fn services(&self) -> [(&'static str, u16); 3] {
+        [
+            ("gdb", self.gdb),
+            ("telnet", self.telnet),
+            ("tcl", self.tcl),
+        ]
+    }
+}
+
+impl Default for Ports {
+    fn default() -> Self {
+        Self::nth(0)
+    }
+}
+
+impl BusyPort {
+    /// Describe the port and its owner to show to the user
+    pub fn describe(&self) -> String {
+        match &self.owner {
+            Some(owner) => format!(
+                "port {} of {} server is used by {} (PID {})",
+                self.port, self.service, owner.name, owner.pid
+            ),
+            None => format!("port {} of {} server is busy", self.port, self.service),
+        }
+    }
+}
+
+/// Check nothing listens on the port on localhost
+pub fn is_free(port: u16) -> bool {
+    TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// Find a process which listens on the port
+///
+/// Listening sockets are looked up in `/proc/net/tcp*`, then the process
+/// which owns the socket inode is found.
+#[cfg(target_os = "linux")]
+pub fn owner(port: u16) -> Option<PortOwner> {
+    use std::fs;
+    use sysinfo::{ProcessExt, System, SystemExt};
+
+    let inodes: Vec<String> = ["/proc/net/tcp", "/proc/net/tcp6"]
+        .iter()
+        .filter_map(|table| fs::read_to_string(table).ok())
+        .flat_map(|table| listening_inodes(&table, port))
+        .collect();
+
+    if inodes.is_empty() {
+        return None;
+    }
+
+    let pid = fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .find(|pid| {
+            fs::read_dir(format!("/proc/{}/fd", pid))
+                .map(|fds| {
+                    fds.flatten().any(|fd| {
+                        fs::read_link(fd.path())
+                            .map(|link| {
+                                inodes
+                                    .iter()
+                                    .any(|inode| link.as_os_str() == inode.as_str())
+                            })
+                            .unwrap_or(false)
+                    })
+                })
+                .unwrap_or(false)
+        })?;
+
+    let mut system = System::new();
+    system.refresh_processes();
+
+    let name = system
+        .process(pid as sysinfo::Pid)
+        .map(|process| process.name().to_string())
+        .unwrap_or_else(|| "unknown process".into());
+
+    Some(PortOwner { pid, name })
+}
+
+/// Return socket inodes listening on the port in a `/proc/net/tcp*` table
+///
+/// Inodes are formatted as links of socket file descriptors.
+#[cfg(target_os = "linux")]
+fn listening_inodes(table: &str, port: u16) -> Vec<String> {
+    const TCP_LISTEN: &str = "0A";
+
+    table
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let local_port = fields.get(1)?.rsplit(':').next()?;
+            let local_port = u16::from_str_radix(local_port, 16).ok()?;
+
+            if local_port == port && *fields.get(3)? == TCP_LISTEN {
+                Some(format!("socket:[{}]", fields.get(9)?))
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn owner(_port: u16) -> Option<PortOwner> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_free, Ports};
+    use std::net::TcpListener;
+
+    fn ports(gdb: u16, telnet: u16, tcl: u16) -> Ports {
+        Ports { gdb, telnet, tcl }
+    }
+
+    #[test]
+    fn nth() {
+        assert_eq!(Ports::default(), ports(3333, 4444, 6666));
+        assert_eq!(Ports::nth(2), ports(3353, 4464, 6686));
+    }
+
+    #[test]
+    fn overlaps() {
+        assert!(Ports::nth(0).overlaps(&Ports::nth(0)));
+        assert!(!Ports::nth(0).overlaps(&Ports::nth(1)));
+        // GDB ports of the other cores of the first session
+        assert!(Ports::nth(0).overlaps(&ports(3342, 5000, 7000)));
+        assert!(ports(3342, 5000, 7000).overlaps(&Ports::nth(0)));
+        assert!(!Ports::nth(0).overlaps(&ports(3343, 5000, 7000)));
+        // Any service against any other one
+        assert!(Ports::nth(0).overlaps(&ports(1000, 2000, 3335)));
+    }
+
+    #[test]
+    fn busy() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let free = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        assert!(!is_free(port));
+        let busy = ports(free, port, free).busy();
+
+        assert_eq!(busy.len(), 1);
+        assert_eq!((busy[0].service, busy[0].port), ("telnet", port));
+        #[cfg(target_os = "linux")]
+        assert_eq!(
+            busy[0].owner.as_ref().map(|owner| owner.pid),
+            Some(std::process::id())
+        );
+    }
+
+    #[test]
+    fn reassign() {
+        let busy = [3333, 3334, 4444, 6666];
+        let is_free = |port: u16| !busy.contains(&port);
+
+        assert_eq!(
+            Ports::nth(0).reassign_with(&[], is_free).unwrap(),
+            ports(3335, 4445, 6667)
+        );
+        // Ports are picked once and free ports are kept
+        assert_eq!(
+            ports(3334, 3334, 7000).reassign_with(&[], is_free).unwrap(),
+            ports(3335, 3336, 7000)
+        );
+    }
+
+    #[test]
+    fn reassign_skips_reserved() {
+        // The next block belongs to another session, which is stopped
+        assert_eq!(
+            Ports::nth(0)
+                .reassign_with(&[Ports::nth(1)], |port| !(3333..3343).contains(&port))
+                .unwrap(),
+            ports(3353, 4444, 6666)
+        );
+        assert_eq!(
+            Ports::nth(0)
+                .reassign_with(&[ports(3334, 5000, 3344)], |port| port != 3333)
+                .unwrap(),
+            ports(3354, 4444, 6666)
+        );
+    }
+
+    #[test]
+    fn reassign_fails() {
+        let error = Ports::nth(0)
+            .reassign_with(&[], |port| port != 4444 && !(6666..6766).contains(&port))
+            .unwrap_err();
+
+        assert_eq!(error.message, "No free port for tcl server!");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn listening_inodes() {
+        // Table in the format of /proc/net/tcp, trailing columns are cut
+        let table = "\
+  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:0D05 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41017 1
+   1: 0100007F:115C 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41018 1
+   2: 0100007F:0D05 0100007F:A3F2 01 00000000:00000000 00:00000000 00000000  1000        0 41230 1
+   3: 00000000:1A0A 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 41019 1
+";
+
+        assert_eq!(super::listening_inodes(table, 3333), vec!["socket:[41017]"]);
+        assert_eq!(super::listening_inodes(table, 4444), vec!["socket:[41018]"]);
+        assert_eq!(super::listening_inodes(table, 6666), vec!["socket:[41019]"]);
+        assert!(super::listening_inodes(table, 5555).is_empty());
+        assert!(super::listening_inodes("", 3333).is_empty());
+    }
+}
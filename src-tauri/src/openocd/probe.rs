@@ -0,0 +1,473 @@
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::openocd::config::Config;
+
+/// Root of USB devices in sysfs
+pub const SYSFS_USB_DEVICES: &str = "/sys/bus/usb/devices";
+
+/// Adapter driver used by OpenOCD to talk with a probe
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Driver {
+    Stlink,
+    CmsisDap,
+    Jlink,
+    Ftdi,
+}
+
+struct KnownProbe {
+    vid: u16,
+    /// `None` matches any product of the vendor
+    pid: Option<u16>,
+    name: &'static str,
+    driver: Driver,
+    /// Names of interface configs preferred for the probe
+    interfaces: &'static [&'static str],
+}
+
+const KNOWN_PROBES: &[KnownProbe] = &[
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x3744),
+        name: "ST-LINK/V1",
+        driver: Driver::Stlink,
+        interfaces: &["stlink", "stlink-v1"],
+    },
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x3748),
+        name: "ST-LINK/V2",
+        driver: Driver::Stlink,
+        interfaces: &["stlink", "stlink-v2"],
+    },
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x374b),
+        name: "ST-LINK/V2-1",
+        driver: Driver::Stlink,
+        interfaces: &["stlink", "stlink-v2-1"],
+    },
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x3752),
+        name: "ST-LINK/V2-1",
+        driver: Driver::Stlink,
+        interfaces: &["stlink", "stlink-v2-1"],
+    },
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x374e),
+        name: "STLINK-V3",
+        driver: Driver::Stlink,
+        interfaces: &["stlink"],
+    },
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x374f),
+        name: "STLINK-V3",
+        driver: Driver::Stlink,
+        interfaces: &["stlink"],
+    },
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x3753),
+        name: "STLINK-V3",
+        driver: Driver::Stlink,
+        interfaces: &["stlink"],
+    },
+    KnownProbe {
+        vid: 0x0483,
+        pid: Some(0x3754),
+        name: "STLINK-V3",
+        driver: Driver::Stlink,
+        interfaces: &["stlink"],
+    },
+    KnownProbe {
+        vid: 0x1366,
+        pid: None,
+        name: "J-Link",
+        driver: Driver::Jlink,
+        interfaces: &["jlink"],
+    },
+    KnownProbe {
+        vid: 0x0d28,
+        pid: Some(0x0204),
+        name: "DAPLink",
+        driver: Driver::CmsisDap,
+        interfaces: &["cmsis-dap"],
+    },
+    KnownProbe {
+        vid: 0x2e8a,
+        pid: Some(0x000c),
+        name: "Raspberry Pi Debug Probe",
+        driver: Driver::CmsisDap,
+        interfaces: &["cmsis-dap"],
+    },
+    KnownProbe {
+        vid: 0x15ba,
+        pid: Some(0x0003),
+        name: "Olimex ARM-USB-OCD",
+        driver: Driver::Ftdi,
+        interfaces: &["ftdi/olimex-arm-usb-ocd"],
+    },
+    KnownProbe {
+        vid: 0x15ba,
+        pid: Some(0x0004),
+        name: "Olimex ARM-USB-TINY",
+        driver: Driver::Ftdi,
+        interfaces: &["ftdi/olimex-jtag-tiny"],
+    },
+    KnownProbe {
+        vid: 0x15ba,
+        pid: Some(0x002a),
+        name: "Olimex ARM-USB-TINY-H",
+        driver: Driver::Ftdi,
+        interfaces: &["ftdi/olimex-arm-usb-tiny-h"],
+    },
+    KnownProbe {
+        vid: 0x15ba,
+        pid: Some(0x002b),
+        name: "Olimex ARM-USB-OCD-H",
+        driver: Driver::Ftdi,
+        interfaces: &["ftdi/olimex-arm-usb-ocd-h"],
+    },
+    KnownProbe {
+        vid: 0x0403,
+        pid: Some(0x6010),
+        name: "FTDI FT2232",
+        driver: Driver::Ftdi,
+        interfaces: &[],
+    },
+    KnownProbe {
+        vid: 0x0403,
+        pid: Some(0x6011),
+        name: "FTDI FT4232",
+        driver: Driver::Ftdi,
+        interfaces: &[],
+    },
+    KnownProbe {
+        vid: 0x0403,
+        pid: Some(0x6014),
+        name: "FTDI FT232H",
+        driver: Driver::Ftdi,
+        interfaces: &[],
+    },
+];
+
+/// Debug probe plugged in via USB
+#[derive(Clone, Serialize)]
+pub struct Probe {
+    pub name: String,
+    pub driver: Driver,
+    pub vid: String,
+    pub pid: String,
+    pub serial: Option<String>,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    /// Name of the device directory in sysfs, e.g. `1-1.2`
+    pub location: String,
+    /// Interface configs which can be used with the probe
+    pub interfaces: Vec<Config>,
+}
+
+struct UsbDevice {
+    vid: u16,
+    pid: u16,
+    serial: Option<String>,
+    manufacturer: Option<String>,
+    product: Option<String>,
+    location: String,
+}
+
+impl UsbDevice {
+    fn read(dir: &Path) -> Option<UsbDevice> {
+        let read_attr = |name: &str| {
+            fs::read_to_string(dir.join(name))
+                .ok()
+                .map(|value| value.trim().to_string())
+                .filter(|value| !value.is_empty())
+        };
+        let read_id = |name: &str| u16::from_str_radix(&read_attr(name)?, 16).ok();
+
+        Some(UsbDevice {
+            vid: read_id("idVendor")?,
+            pid: read_id("idProduct")?,
+            serial: read_attr("serial"),
+            manufacturer: read_attr("manufacturer"),
+            product: read_attr("product"),
+            location: dir.file_name()?.to_string_lossy().to_string(),
+        })
+    }
+
+    fn is_cmsis_dap(&self) -> bool {
+        matches!(&self.product, Some(product) if product.contains("CMSIS-DAP"))
+    }
+}
+
+/// Return debug probes plugged in via USB
+///
+/// Known probes are matched by VID/PID, any device with "CMSIS-DAP" in its
+/// product string is reported as a CMSIS-DAP probe.
+///
+/// # Arguments
+///
+/// * `sysfs` — directory with USB devices, usually `SYSFS_USB_DEVICES`.
+/// * `interfaces` — interface configs to match probes with.
+///
+pub fn discover(sysfs: &Path, interfaces: &[Config]) -> Vec<Probe> {
+    let entries = match fs::read_dir(sysfs) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut probes: Vec<Probe> = entries
+        .flatten()
+        .filter_map(|entry| UsbDevice::read(&entry.path()))
+        .filter_map(|device| identify(device, interfaces))
+        .collect();
+
+    probes.sort_by(|a, b| a.location.cmp(&b.location));
+    probes
+}
+
+fn identify(device: UsbDevice, interfaces: &[Config]) -> Option<Probe> {
+    let known = KNOWN_PROBES.iter().find(|known| {
+        known.vid == device.vid && (known.pid.is_none() || known.pid == Some(device.pid))
+    });
+
+    let (name, driver, preferred) = match known {
+        Some(known) => (known.name.to_string(), known.driver, known.interfaces),
+        None if device.is_cmsis_dap() => (
+            device.product.clone().unwrap_or_default(),
+            Driver::CmsisDap,
+            &["cmsis-dap"][..],
+        ),
+        None => return None,
+    };
+
+    Some(Probe {
+        name,
+        driver,
+        vid: format!("{:04x}", device.vid),
+        pid: format!("{:04x}", device.pid),
+        interfaces: match_interfaces(device.vid, device.pid, preferred, interfaces),
+        serial: device.serial,
+        manufacturer: device.manufacturer,
+        product: device.product,
+        location: device.location,
+    })
+}
+
+/// Select interface configs for a probe
+///
+/// Preferred configs go first, then any config which declares the probe
+/// VID/PID pair with a `vid_pid` command.
+fn match_interfaces(vid: u16, pid: u16, preferred: &[&str], interfaces: &[Config]) -> Vec<Config> {
+    let mut matched: Vec<Config> = preferred
+        .iter()
+        .filter_map(|name| interfaces.iter().find(|config| config.name == *name))
+        .cloned()
+        .collect();
+
+    for config in interfaces {
+        let is_matched = matched.iter().any(|m| m.path == config.path);
+        if !is_matched && declares_vid_pid(Path::new(&config.path), vid, pid) {
+            matched.push(config.clone());
+        }
+    }
+
+    matched
+}
+
+fn declares_vid_pid(config: &Path, vid: u16, pid: u16) -> bool {
+    lazy_static! {
+        static ref VID_PID: Regex =
+            Regex::new(r"(?m)^\s*[\w ]*vid_pid((?:\s+0x[0-9a-fA-F]+\s+0x[0-9a-fA-F]+)+)").unwrap();
+        static ref HEX: Regex = Regex::new(r"0x([0-9a-fA-F]+)").unwrap();
+    }
+
+    let content = match fs::read_to_string(config) {
+        Ok(content) => content,
+        Err(_) => return false,
+    };
+
+    VID_PID.captures_iter(&content).any(|caps| {
+        let ids: Vec<u16> = HEX
+            .captures_iter(&caps[1])
+            .filter_map(|id| u16::from_str_radix(&id[1], 16).ok())
+            .collect();
+
+        ids.chunks(2).any(|pair| pair == [vid, pid])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{discover, Driver, Probe};
+    use crate::openocd::config::Config;
+    use crate::openocd::testing::TempDir;
+
+    /// Write attributes of a USB device the way sysfs shows them
+    fn device(sysfs: &TempDir, location: &str, attrs: &[(&str, &str)]) {
+        for (name, value) in attrs {
+            sysfs.write(&format!("{}/{}", location, name), &format!("{}\n", value));
+        }
+    }
+
+    fn interfaces(scripts: &TempDir) -> Vec<Config> {
+        let configs = [
+            (
+                "stlink",
+                "adapter driver hla\nhla_layout stlink\nhla_vid_pid 0x0483 0x3744 0x0483 0x3748\n",
+            ),
+            ("stlink-v2", "source [find interface/stlink.cfg]\n"),
+            ("cmsis-dap", "adapter driver cmsis-dap\n"),
+            ("jlink", "adapter driver jlink\n"),
+            (
+                "ftdi/olimex-arm-usb-ocd-h",
+                "adapter driver ftdi\nftdi vid_pid 0x15ba 0x002b\n",
+            ),
+            (
+                "vendor/stlink-clone",
+                "adapter driver hla\nhla_vid_pid 0x0483 0x3748\n",
+            ),
+        ];
+
+        configs
+            .iter()
+            .map(|(name, content)| Config {
+                name: name.to_string(),
+                path: scripts
+                    .write(&format!("interface/{}.cfg", name), content)
+                    .display()
+                    .to_string(),
+                ..Config::default()
+            })
+            .collect()
+    }
+
+    fn names(probe: &Probe) -> Vec<&str> {
+        probe
+            .interfaces
+            .iter()
+            .map(|config| config.name.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn discover_probes() {
+        let sysfs = TempDir::new("sysfs");
+        let scripts = TempDir::new("scripts");
+        let interfaces = interfaces(&scripts);
+
+        device(
+            &sysfs,
+            "1-1",
+            &[
+                ("idVendor", "0483"),
+                ("idProduct", "3748"),
+                ("serial", "066DFF485550755187121723"),
+                ("manufacturer", "STMicroelectronics"),
+                ("product", "STM32 STLink"),
+            ],
+        );
+        // Interface of the device, has no IDs
+        device(&sysfs, "1-1:1.0", &[("bInterfaceClass", "ff")]);
+        device(
+            &sysfs,
+            "1-2",
+            &[
+                ("idVendor", "c251"),
+                ("idProduct", "f001"),
+                ("serial", "0001A0000000"),
+                ("product", "LPC-Link CMSIS-DAP"),
+            ],
+        );
+        device(
+            &sysfs,
+            "1-3",
+            &[
+                ("idVendor", "046d"),
+                ("idProduct", "c52b"),
+                ("product", "USB Receiver"),
+            ],
+        );
+        device(
+            &sysfs,
+            "2-1",
+            &[
+                ("idVendor", "1366"),
+                ("idProduct", "0105"),
+                ("manufacturer", "SEGGER"),
+                ("product", "J-Link"),
+            ],
+        );
+        device(
+            &sysfs,
+            "usb1",
+            &[("idVendor", "1d6b"), ("idProduct", "0002")],
+        );
+
+        let probes = discover(sysfs.path(), &interfaces);
+        let locations: Vec<&str> = probes.iter().map(|probe| probe.location.as_str()).collect();
+        assert_eq!(locations, vec!["1-1", "1-2", "2-1"]);
+
+        let stlink = &probes[0];
+        assert_eq!(stlink.name, "ST-LINK/V2");
+        assert_eq!(stlink.driver, Driver::Stlink);
+        assert_eq!((stlink.vid.as_str(), stlink.pid.as_str()), ("0483", "3748"));
+        assert_eq!(stlink.serial.as_deref(), Some("066DFF485550755187121723"));
+        // Preferred configs go first, then configs declaring the VID/PID
+        assert_eq!(
+            names(stlink),
+            vec!["stlink", "stlink-v2", "vendor/stlink-clone"]
+        );
+
+        let cmsis_dap = &probes[1];
+        assert_eq!(cmsis_dap.name, "LPC-Link CMSIS-DAP");
+        assert_eq!(cmsis_dap.driver, Driver::CmsisDap);
+        assert_eq!(cmsis_dap.vid, "c251");
+        assert_eq!(names(cmsis_dap), vec!["cmsis-dap"]);
+
+        let jlink = &probes[2];
+        assert_eq!(jlink.name, "J-Link");
+        assert_eq!(jlink.driver, Driver::Jlink);
+        assert_eq!(jlink.serial, None);
+        assert_eq!(jlink.manufacturer.as_deref(), Some("SEGGER"));
+        assert_eq!(names(jlink), vec!["jlink"]);
+    }
+
+    #[test]
+    fn match_by_vid_pid_declaration() {
+        let sysfs = TempDir::new("sysfs");
+        let scripts = TempDir::new("scripts");
+        let interfaces = interfaces(&scripts);
+
+        device(
+            &sysfs,
+            "3-2",
+            &[
+                ("idVendor", "15ba"),
+                ("idProduct", "002b"),
+                ("serial", "OL45B2C4"),
+            ],
+        );
+
+        let probes = discover(sysfs.path(), &interfaces);
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].driver, Driver::Ftdi);
+        assert_eq!(names(&probes[0]), vec!["ftdi/olimex-arm-usb-ocd-h"]);
+    }
+
+    #[test]
+    fn missing_sysfs() {
+        let sysfs = TempDir::new("sysfs");
+        let missing = sysfs.path().join("missing");
+
+        assert!(discover(&missing, &[]).is_empty());
+    }
+}
@@ -1,46 +1,217 @@
+use command_group::{CommandGroup, GroupChild};
+use log::warn;
+use serde::{Deserialize, Serialize};
 use std::convert::TryInto;
+use std::io::Read;
 use std::option::Option;
 use std::path::Path;
-use std::process::{Child, Command, Stdio};
+use std::process::{Command, ExitStatus, Stdio};
 use std::result::Result;
+use std::thread;
+use std::time::{Duration, Instant};
 use sysinfo::{ProcessExt, Signal, System, SystemExt};
 use which::which;
 
-use crate::openocd::config::Config;
+use crate::error::ErrorMsg;
+use crate::openocd::{config::Config, ports::Ports, version::Capabilities};
+
+/// Max run time of OpenOCD started to print its version or probe commands
+const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Pause between checks of the process started by `start_exec`
+const EXEC_POLL_INTERVAL: Duration = Duration::from_millis(20);
 
 pub fn is_available() -> bool {
     which("openocd").is_ok()
 }
 
 #[cfg(target_os = "linux")]
-fn spawn(args: Vec<&str>) -> Result<Child, std::io::Error> {
+fn spawn(args: Vec<String>, stdout: Stdio) -> Result<GroupChild, std::io::Error> {
     Command::new("openocd")
         .args(args)
+        .stdout(stdout)
         .stderr(Stdio::piped())
-        .spawn()
+        .group_spawn()
 }
 
 #[cfg(target_os = "windows")]
-fn spawn(args: Vec<&str>) -> Result<Child, std::io::Error> {
+fn spawn(args: Vec<String>, stdout: Stdio) -> Result<GroupChild, std::io::Error> {
     use std::os::windows::process::CommandExt;
     use winapi::um::winbase::CREATE_NO_WINDOW;
 
     Command::new("openocd")
         .creation_flags(CREATE_NO_WINDOW)
         .args(args)
+        .stdout(stdout)
         .stderr(Stdio::piped())
-        .spawn()
+        .group_spawn()
 }
 
-pub fn start(config: &[Config]) -> Option<Child> {
-    if is_available() {
-        let args = config
-            .iter()
-            .map(|config| ["-f", config.path.as_str()])
-            .flatten()
-            .collect::<Vec<&str>>();
+/// Options of OpenOCD launch besides configs
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LaunchOptions {
+    /// Serial number of the probe to use if several probes are attached
+    pub adapter_serial: Option<String>,
+    /// Clock of the debug interface, the config default is used if not set
+    pub adapter_speed_khz: Option<u32>,
+    /// Replace busy gdb, telnet and tcl ports with free ones instead of failing
+    pub auto_ports: bool,
+    /// Enable ARM semihosting after init, its output is read from stdout
+    pub semihosting: bool,
+}
 
-        let thread = spawn(args);
+impl LaunchOptions {
+    pub fn validate(&self) -> Result<(), ErrorMsg> {
+        let is_serial_valid = match &self.adapter_serial {
+            Some(serial) => {
+                !serial.is_empty()
+                    && serial
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+            }
+            None => true,
+        };
+
+        if !is_serial_valid {
+            return Err(
+                "Bad adapter serial: only latin letters, digits and \"-_.:\" are allowed!".into(),
+            );
+        }
+        if self.adapter_speed_khz == Some(0) {
+            return Err("Bad adapter speed: must be greater than 0 kHz!".into());
+        }
+        Ok(())
+    }
+}
+
+fn command_args(commands: Vec<String>) -> Vec<String> {
+    commands
+        .into_iter()
+        .flat_map(|command| vec!["-c".to_string(), command])
+        .collect()
+}
+
+/// Build `-c` arguments which set ports of OpenOCD servers
+fn ports_args(ports: &Ports) -> Vec<String> {
+    command_args(vec![
+        format!("gdb_port {}", ports.gdb),
+        format!("telnet_port {}", ports.telnet),
+        format!("tcl_port {}", ports.tcl),
+    ])
+}
+
+/// Build `-c` arguments which select the probe by its serial number
+///
+/// `adapter serial` is available since OpenOCD 0.12. Older versions have
+/// a separate command for every adapter driver, so all of them are tried.
+/// Both forms are tried if the version of OpenOCD is unknown.
+/// Commands of adapter drivers exist only after the driver is selected,
+/// so the arguments must follow the interface config.
+fn serial_args(serial: &str, capabilities: &Capabilities) -> Vec<String> {
+    let legacy = [
+        "hla_serial",
+        "cmsis_dap_serial",
+        "ftdi_serial",
+        "jlink serial",
+    ]
+    .iter()
+    .map(|command| format!("catch {{{} {}}}", command, serial))
+    .collect::<Vec<String>>()
+    .join("; ");
+
+    let command = if !capabilities.probed {
+        format!(
+            "if {{[catch {{adapter serial {}}}]}} {{{}}}",
+            serial, legacy
+        )
+    } else if capabilities.adapter_serial {
+        format!("adapter serial {}", serial)
+    } else {
+        legacy
+    };
+
+    command_args(vec![command])
+}
+
+/// Build `-c` arguments which set the clock of the debug interface
+///
+/// `adapter speed` has replaced `adapter_khz` in OpenOCD 0.11.
+fn speed_args(khz: u32, capabilities: &Capabilities) -> Vec<String> {
+    let command = if !capabilities.probed {
+        format!(
+            "if {{[catch {{adapter speed {0}}}]}} {{adapter_khz {0}}}",
+            khz
+        )
+    } else if capabilities.adapter_speed {
+        format!("adapter speed {}", khz)
+    } else {
+        format!("adapter_khz {}", khz)
+    };
+
+    command_args(vec![command])
+}
+
+/// Build `-c` arguments which disable OpenOCD servers for one-shot runs
+fn disabled_ports_args() -> Vec<String> {
+    command_args(vec![
+        "gdb_port disabled".to_string(),
+        "telnet_port disabled".to_string(),
+        "tcl_port disabled".to_string(),
+    ])
+}
+
+/// Build `-c` arguments which enable semihosting
+///
+/// `arm semihosting` is a command of the target, so the target is
+/// initialized first.
+fn semihosting_args() -> Vec<String> {
+    command_args(vec![
+        "init".to_string(),
+        "arm semihosting enable".to_string(),
+    ])
+}
+
+/// Build arguments of OpenOCD
+fn args(config: &[Config], ports: &Ports, options: &LaunchOptions) -> Vec<String> {
+    let mut args = ports_args(ports);
+    args.extend(config_args(config, options));
+    if options.semihosting {
+        args.extend(semihosting_args());
+    }
+    args
+}
+
+/// Build `-f` arguments of configs followed by arguments of launch options
+fn config_args(config: &[Config], options: &LaunchOptions) -> Vec<String> {
+    let mut args: Vec<String> = config
+        .iter()
+        .flat_map(|config| vec!["-f".to_string(), config.path.clone()])
+        .collect();
+
+    let capabilities = Capabilities::get();
+    if let Some(serial) = &options.adapter_serial {
+        args.extend(serial_args(serial, capabilities));
+    }
+    if let Some(khz) = options.adapter_speed_khz {
+        args.extend(speed_args(khz, capabilities));
+    }
+
+    args
+}
+
+/// Start OpenOCD with its servers
+///
+/// Stdout is piped if semihosting is enabled, since OpenOCD writes the
+/// output of the target there.
+pub fn start(config: &[Config], ports: &Ports, options: &LaunchOptions) -> Option<GroupChild> {
+    if is_available() {
+        let stdout = if options.semihosting {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        };
+        let thread = spawn(args(config, ports, options), stdout);
         if let Ok(thread) = thread {
             Some(thread)
         } else {
@@ -51,17 +222,129 @@ pub fn start(config: &[Config]) -> Option<Child> {
     }
 }
 
-#[cfg_attr(unix, allow(dead_code))]
+/// Start OpenOCD which runs commands and exits without starting servers
+///
+/// Commands are run after the configs, so they must end with `exit` or
+/// `shutdown`.
+pub fn run_once(
+    config: &[Config],
+    options: &LaunchOptions,
+    commands: Vec<String>,
+) -> Option<GroupChild> {
+    if !is_available() {
+        return None;
+    }
+
+    let mut args = disabled_ports_args();
+    args.extend(config_args(config, options));
+    args.extend(command_args(commands));
+
+    spawn(args, Stdio::inherit()).ok()
+}
+
+/// Run OpenOCD without configs and servers and return its output
+///
+/// Used to probe commands of the installed OpenOCD, the commands are
+/// followed by `shutdown`.
+pub fn probe(commands: Vec<String>) -> Option<String> {
+    if !is_available() {
+        return None;
+    }
+
+    let mut args = disabled_ports_args();
+    args.extend(command_args(commands));
+    args.extend(command_args(vec!["shutdown".to_string()]));
+
+    start_exec(Path::new("openocd"), args)
+}
+
+/// Run an executable and return its stderr
+///
+/// The process is killed if it runs longer than `EXEC_TIMEOUT`, e.g. when
+/// OpenOCD is stuck at an adapter.
 pub fn start_exec(exe_path: &Path, args: Vec<String>) -> Option<String> {
-    let out = Command::new(exe_path).args(args).output();
+    exec_with_timeout(exe_path, args, EXEC_TIMEOUT)
+}
+
+fn exec_with_timeout(exe_path: &Path, args: Vec<String>, timeout: Duration) -> Option<String> {
+    let mut child = Command::new(exe_path)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    // Stderr is read in a thread, so a full pipe doesn't block the process
+    let mut stderr = child.stderr.take()?;
+    let reader = thread::spawn(move || {
+        let mut output = Vec::new();
+        stderr.read_to_end(&mut output).ok();
+        output
+    });
+
+    let started = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if started.elapsed() < timeout => thread::sleep(EXEC_POLL_INTERVAL),
+            Ok(None) => {
+                warn!(
+                    "{} was killed after {:?} of run",
+                    exe_path.display(),
+                    timeout
+                );
+                child.kill().ok();
+                child.wait().ok();
+                return None;
+            }
+            Err(_) => return None,
+        }
+    }
 
-    match out {
-        Ok(out) => Some(String::from_utf8_lossy(&out.stderr).to_string()),
-        Err(_) => None,
+    reader
+        .join()
+        .ok()
+        .map(|output| String::from_utf8_lossy(&output).to_string())
+}
+
+/// Split exit status of a process to an exit code and a signal number
+///
+/// Signal is always `None` on Windows.
+pub fn exit_reason(status: ExitStatus) -> (Option<i32>, Option<i32>) {
+    #[cfg(unix)]
+    let signal = {
+        use std::os::unix::process::ExitStatusExt;
+        status.signal()
+    };
+
+    #[cfg(not(unix))]
+    let signal = None;
+
+    (status.code(), signal)
+}
+
+/// Ask the process group of OpenOCD to terminate with SIGTERM
+///
+/// Return `false` if the signal was not sent. Always `false` on Windows.
+#[cfg_attr(not(unix), allow(unused_variables))]
+pub fn terminate_proc(proc: &mut GroupChild) -> bool {
+    #[cfg(unix)]
+    {
+        use command_group::{Signal, UnixChildExt};
+        proc.signal(Signal::SIGTERM).is_ok()
+    }
+
+    #[cfg(not(unix))]
+    {
+        false
     }
 }
 
-pub fn kill_proc(proc: &mut Child) -> bool {
+/// Kill OpenOCD process and its process group
+///
+/// Return `true` if the process was killed.
+pub fn kill_proc(proc: &mut GroupChild) -> bool {
     // Convert all PIDs to unix format
     let openocd_pid: i32 = proc.id().try_into().unwrap();
 
@@ -84,5 +367,44 @@ pub fn kill_proc(proc: &mut Child) -> bool {
     }
 
     // Otherwise just kill the process
-    proc.kill().is_err()
+    proc.kill().is_ok()
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::exec_with_timeout;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    fn sh(script: &str, timeout: Duration) -> Option<String> {
+        exec_with_timeout(Path::new("sh"), vec!["-c".into(), script.into()], timeout)
+    }
+
+    #[test]
+    fn exec_returns_stderr() {
+        assert_eq!(
+            sh("echo out; echo err >&2", Duration::from_secs(5)).as_deref(),
+            Some("err\n")
+        );
+    }
+
+    #[test]
+    fn exec_is_killed_on_timeout() {
+        let started = Instant::now();
+
+        assert_eq!(sh("sleep 10", Duration::from_millis(100)), None);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn exec_of_missing_binary() {
+        assert_eq!(
+            exec_with_timeout(
+                Path::new("/nonexistent/openocd"),
+                Vec::new(),
+                Duration::from_secs(1)
+            ),
+            None
+        );
+    }
 }
@@ -0,0 +1,330 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use crate::error::ErrorMsg;
+
+/// Default port of the OpenOCD TCL server
+pub const DEFAULT_PORT: u16 = 6666;
+
+/// Every message of the TCL server protocol is terminated by this byte
+const TERMINATOR: u8 = 0x1a;
+
+/// TCL variable holding the result of a command run by `execute_checked`
+const RESULT_VAR: &str = "_jrmd_result";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(2);
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client of the OpenOCD TCL server
+///
+/// Commands are sent as plain TCL strings terminated by `0x1a`. OpenOCD
+/// answers with the result of the command terminated by the same byte.
+pub struct TclClient {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl TclClient {
+    /// Connect to the TCL server of OpenOCD started on localhost
+    pub fn connect(port: u16) -> Result<TclClient, ErrorMsg> {
+        Self::connect_to(("127.0.0.1", port))
+    }
+
+    /// Connect to the TCL server listening on any address
+    pub fn connect_to(addr: impl ToSocketAddrs) -> Result<TclClient, ErrorMsg> {
+        let addr: SocketAddr = addr
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| ErrorMsg::from("Bad address of OpenOCD TCL server!"))?;
+
+        let stream = TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT)
+            .map_err(|e| format!("Can't connect to OpenOCD TCL server at {}: {}", addr, e))?;
+
+        stream
+            .set_read_timeout(Some(READ_TIMEOUT))
+            .and_then(|_| stream.set_nodelay(true))
+            .map_err(|e| format!("Can't configure OpenOCD TCL connection: {}", e))?;
+
+        let reader = stream
+            .try_clone()
+            .map(BufReader::new)
+            .map_err(|e| format!("Can't configure OpenOCD TCL connection: {}", e))?;
+
+        Ok(TclClient { stream, reader })
+    }
+
+    /// Send a TCL command and wait for its response
+    ///
+    /// Return the response without the terminating byte.
+    pub fn execute(&mut self, command: &str) -> Result<String, ErrorMsg> {
+        let mut request = command.trim().as_bytes().to_vec();
+        request.push(TERMINATOR);
+
+        self.stream
+            .write_all(&request)
+            .map_err(|e| format!("Can't send command to OpenOCD: {}", e))?;
+
+        self.read_message()
+    }
+
+    /// Send a TCL command and fail if the command has thrown an error
+    ///
+    /// The TCL server returns an error message the same way as a result, so
+    /// the command is wrapped with `catch` to tell them apart. The code and
+    /// the result are returned by a single request: the interpreter is
+    /// shared by all connections, another one could overwrite the variable
+    /// in between of two requests.
+    pub fn execute_checked(&mut self, command: &str) -> Result<String, ErrorMsg> {
+        let response = self.execute(&format!(
+            "format {{%d %s}} [catch {{{}}} {}] ${}",
+            command.trim(),
+            RESULT_VAR,
+            RESULT_VAR
+        ))?;
+
+        let mut parts = response.splitn(2, ' ');
+        let code = parts.next().unwrap_or_default();
+        let result = parts.next().unwrap_or_default().to_string();
+
+        if code == "0" {
+            Ok(result)
+        } else {
+            Err(result.into())
+        }
+    }
+
+    /// Check the command is known to OpenOCD
+    ///
+    /// Used to choose between commands of different OpenOCD versions.
+    pub fn has_command(&mut self, name: &str) -> Result<bool, ErrorMsg> {
+        let found = self.execute(&format!("info commands {}", name))?;
+        Ok(found.split_whitespace().any(|command| command == name))
+    }
+
+    /// Change the time to wait for a response, e.g. for long running commands
+    pub fn set_read_timeout(&mut self, timeout: Duration) -> Result<(), ErrorMsg> {
+        self.stream
+            .set_read_timeout(Some(timeout))
+            .map_err(|e| format!("Can't configure OpenOCD TCL connection: {}", e).into())
+    }
+
+    /// Enable notifications of target events on this connection
+    ///
+    /// Notifications arrive in between of responses, so the connection must
+    /// only wait for them with `read_notification` after that.
+    pub fn enable_notifications(&mut self) -> Result<(), ErrorMsg> {
+        self.execute_checked("tcl_notifications on")?;
+        self.stream
+            .set_read_timeout(None)
+            .map_err(|e| format!("Can't configure OpenOCD TCL connection: {}", e).into())
+    }
+
+    /// Wait for the next notification, e.g. `type target_event event halted`
+    pub fn read_notification(&mut self) -> Result<String, ErrorMsg> {
+        self.read_message()
+    }
+
+    /// Return a handle of the connection to shut it down from another thread
+    pub fn try_clone_stream(&self) -> Result<TcpStream, ErrorMsg> {
+        self.stream
+            .try_clone()
+            .map_err(|e| format!("Can't configure OpenOCD TCL connection: {}", e).into())
+    }
+
+    fn read_message(&mut self) -> Result<String, ErrorMsg> {
+        let mut response = Vec::new();
+
+        let size = self
+            .reader
+            .read_until(TERMINATOR, &mut response)
+            .map_err(|e| format!("Can't read response from OpenOCD: {}", e))?;
+
+        if size == 0 || response.pop() != Some(TERMINATOR) {
+            return Err("OpenOCD closed the TCL connection!".into());
+        }
+
+        Ok(String::from_utf8_lossy(&response).to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TclClient, TERMINATOR};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    /// Fake TCL server accepting one connection, returns the requests it got
+    fn serve<F>(handler: F) -> (u16, JoinHandle<Vec<String>>)
+    where
+        F: FnOnce(&mut FakeConnection) + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut connection = FakeConnection {
+                reader: BufReader::new(stream.try_clone().unwrap()),
+                stream,
+                requests: Vec::new(),
+            };
+            handler(&mut connection);
+            connection.requests
+        });
+
+        (port, server)
+    }
+
+    struct FakeConnection {
+        stream: TcpStream,
+        reader: BufReader<TcpStream>,
+        requests: Vec<String>,
+    }
+
+    impl FakeConnection {
+        fn request(&mut self) -> String {
+            let mut request = Vec::new();
+            self.reader.read_until(TERMINATOR, &mut request).unwrap();
+            assert_eq!(request.pop(), Some(TERMINATOR));
+
+            let request = String::from_utf8(request).unwrap();
+            self.requests.push(request.clone());
+            request
+        }
+
+        fn reply(&mut self, response: &str) {
+            self.write(response.as_bytes());
+            self.write(&[TERMINATOR]);
+        }
+
+        fn write(&mut self, data: &[u8]) {
+            self.stream.write_all(data).unwrap();
+            self.stream.flush().unwrap();
+        }
+
+        /// Wait for the client to close the connection, fail on more requests
+        fn expect_closed(&mut self) {
+            let mut rest = Vec::new();
+            self.reader.read_until(TERMINATOR, &mut rest).unwrap();
+            assert!(rest.is_empty(), "Unexpected request {:?}", rest);
+        }
+    }
+
+    #[test]
+    fn execute() {
+        let (port, server) = serve(|connection| {
+            connection.request();
+            connection.reply("Open On-Chip Debugger 0.12.0");
+            connection.expect_closed();
+        });
+
+        let mut client = TclClient::connect(port).unwrap();
+        let response = client.execute("  version\n").unwrap();
+        drop(client);
+
+        assert_eq!(response, "Open On-Chip Debugger 0.12.0");
+        assert_eq!(server.join().unwrap(), vec!["version"]);
+    }
+
+    #[test]
+    fn execute_checked_ok() {
+        let (port, server) = serve(|connection| {
+            connection.request();
+            connection.reply("0 pc (/32): 0x08000144");
+            connection.expect_closed();
+        });
+
+        let mut client = TclClient::connect(port).unwrap();
+        let response = client.execute_checked("reg pc").unwrap();
+        drop(client);
+
+        assert_eq!(response, "pc (/32): 0x08000144");
+        // Code and result come in one request, a second one could read the
+        // variable overwritten by another connection
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["format {%d %s} [catch {reg pc} _jrmd_result] $_jrmd_result"]
+        );
+    }
+
+    #[test]
+    fn execute_checked_empty_result() {
+        let (port, server) = serve(|connection| {
+            connection.request();
+            connection.reply("0 ");
+        });
+
+        let mut client = TclClient::connect(port).unwrap();
+        assert_eq!(client.execute_checked("halt").unwrap(), "");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn execute_checked_error() {
+        let (port, server) = serve(|connection| {
+            connection.request();
+            connection.reply("1 invalid command name \"foo\"");
+        });
+
+        let mut client = TclClient::connect(port).unwrap();
+        let error = client.execute_checked("foo").unwrap_err();
+
+        assert_eq!(error.message, "invalid command name \"foo\"");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn has_command() {
+        let (port, server) = serve(|connection| {
+            connection.request();
+            connection.reply("rtt");
+            connection.request();
+            connection.reply("");
+        });
+
+        let mut client = TclClient::connect(port).unwrap();
+        assert!(client.has_command("rtt").unwrap());
+        assert!(!client.has_command("tpiu").unwrap());
+
+        assert_eq!(
+            server.join().unwrap(),
+            vec!["info commands rtt", "info commands tpiu"]
+        );
+    }
+
+    #[test]
+    fn response_split_across_writes() {
+        let (port, server) = serve(|connection| {
+            connection.request();
+            for part in ["0 target ", "halted due to ", "debug-request"] {
+                connection.write(part.as_bytes());
+                thread::sleep(Duration::from_millis(20));
+            }
+            connection.write(&[TERMINATOR]);
+        });
+
+        let mut client = TclClient::connect(port).unwrap();
+        let response = client.execute_checked("halt").unwrap();
+
+        assert_eq!(response, "target halted due to debug-request");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn peer_closed_mid_message() {
+        let (port, server) = serve(|connection| {
+            connection.request();
+            connection.write(b"Open On-Chip");
+        });
+
+        let mut client = TclClient::connect(port).unwrap();
+        let error = client.execute("version").unwrap_err();
+
+        assert_eq!(error.message, "OpenOCD closed the TCL connection!");
+        server.join().unwrap();
+    }
+}
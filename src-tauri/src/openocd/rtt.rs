@@ -0,0 +1,459 @@
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use crate::api::TauriEvent;
+use crate::defmt;
+use crate::error::ErrorMsg;
+use crate::notification::Notification;
+use crate::openocd::{
+    events::RttOutput, ports, rpc::TclClient, session::SessionWindow, version::Capabilities,
+};
+
+/// First port tried for RTT servers, the same as the port of SEGGER RTT telnet
+const BASE_PORT: u16 = 19021;
+
+/// Count of ports tried while searching for a free port of an RTT server
+const SEARCH_RANGE: u16 = 100;
+
+/// ID of the control block written by SEGGER RTT library
+pub const DEFAULT_ID: &str = "SEGGER RTT";
+
+/// Name of the up-channel created by `defmt-rtt`
+const DEFMT_CHANNEL: &str = "defmt";
+
+/// Memory range to search the RTT control block in
+#[derive(Clone, Debug, Deserialize)]
+pub struct RttSetup {
+    /// Start address of the search, usually the start of RAM
+    pub address: u64,
+    /// Size of the searched range in bytes
+    pub size: u64,
+    /// ID of the control block, `SEGGER RTT` if not provided
+    pub id: Option<String>,
+    /// Path to the firmware ELF to decode `defmt` frames with
+    pub elf: Option<String>,
+}
+
+impl RttSetup {
+    fn validate(&self) -> Result<(), ErrorMsg> {
+        let is_id_valid = match &self.id {
+            Some(id) => !id.is_empty() && id.len() < 16 && !id.contains(&['{', '}', '\\'][..]),
+            None => true,
+        };
+
+        if !is_id_valid {
+            return Err(
+                "Bad RTT control block ID: up to 15 chars without braces are allowed!".into(),
+            );
+        }
+        if self.size == 0 {
+            return Err("RTT search range is empty!".into());
+        }
+        Ok(())
+    }
+}
+
+/// RTT channel of the target
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Channel {
+    pub index: u32,
+    pub name: String,
+    /// Size of the channel buffer in bytes
+    pub size: u32,
+    pub flags: u32,
+}
+
+/// Channels found in the RTT control block
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Channels {
+    /// Channels from the target to the host
+    pub up: Vec<Channel>,
+    /// Channels from the host to the target
+    pub down: Vec<Channel>,
+    /// Up-channels decoded as `defmt` frames
+    pub defmt: Vec<u32>,
+}
+
+/// Connection to an OpenOCD RTT server of a channel
+struct Server {
+    channel: u32,
+    port: u16,
+    stream: TcpStream,
+}
+
+/// Running RTT of an OpenOCD session
+///
+/// An OpenOCD RTT server is started for every channel index. Output of
+/// up-channels is emitted as `app://openocd/rtt` event of the session,
+/// input is written to down-channels through the same servers. Servers and
+/// RTT are stopped on drop.
+///
+/// If the firmware ELF is provided, `defmt` up-channels are also decoded to
+/// log records emitted as `app://openocd/defmt` event. The channel named
+/// `defmt` is decoded, or channel 0 if there is no such channel.
+pub struct Rtt {
+    tcl_port: u16,
+    channels: Channels,
+    servers: Vec<Server>,
+}
+
+impl Rtt {
+    /// Find the control block and start streaming of its channels
+    ///
+    /// Return error if the control block was not found or the ELF has no
+    /// `defmt` data.
+    pub fn start(tcl_port: u16, setup: &RttSetup, window: SessionWindow) -> Result<Rtt, ErrorMsg> {
+        setup.validate()?;
+
+        let capabilities = Capabilities::get();
+        if capabilities.probed && !capabilities.rtt {
+            return Err(
+                "RTT is not supported by the installed OpenOCD, 0.11 or newer is required!".into(),
+            );
+        }
+
+        let elf = match &setup.elf {
+            Some(path) => Some(Arc::new(defmt::Elf::load(path)?)),
+            None => None,
+        };
+
+        let mut tcl = TclClient::connect(tcl_port)?;
+        tcl.execute_checked(&format!(
+            "rtt setup {:#x} {} {{{}}}",
+            setup.address,
+            setup.size,
+            setup.id.as_deref().unwrap_or(DEFAULT_ID)
+        ))?;
+        tcl.execute_checked("rtt start")?;
+
+        // RTT and servers already started are stopped by drop on error
+        let mut rtt = Rtt {
+            tcl_port,
+            channels: Channels::default(),
+            servers: Vec::new(),
+        };
+
+        rtt.channels = tcl
+            .execute_checked("rtt channels")
+            .map_err(|e| format!("RTT control block is not found: {}", e.message.trim()))
+            .map(|response| parse_channels(&response))?;
+
+        if elf.is_some() {
+            rtt.channels.defmt = defmt_channels(&rtt.channels.up);
+        }
+
+        let mut indexes: Vec<u32> = rtt
+            .channels
+            .up
+            .iter()
+            .chain(rtt.channels.down.iter())
+            .map(|channel| channel.index)
+            .collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+
+        for channel in indexes {
+            let server = Self::start_server(&mut tcl, channel, &rtt.servers)?;
+
+            let reader = server
+                .stream
+                .try_clone()
+                .map_err(|e| format!("Can't configure RTT connection: {}", e))?;
+            let window = window.clone();
+            let elf = elf
+                .clone()
+                .filter(|_| rtt.channels.defmt.contains(&channel));
+            thread::spawn(move || Self::read_output(reader, channel, elf, window));
+
+            rtt.servers.push(server);
+        }
+
+        info!("RTT started: {:?}", rtt.channels);
+        Ok(rtt)
+    }
+
+    pub fn channels(&self) -> &Channels {
+        &self.channels
+    }
+
+    /// Write data to a down-channel
+    pub fn send(&mut self, channel: u32, data: &[u8]) -> Result<(), ErrorMsg> {
+        if !self.channels.down.iter().any(|down| down.index == channel) {
+            return Err(format!("RTT down-channel {} not found!", channel).into());
+        }
+
+        let server = self
+            .servers
+            .iter_mut()
+            .find(|server| server.channel == channel)
+            .ok_or_else(|| ErrorMsg::from(format!("RTT channel {} is not connected!", channel)))?;
+
+        server
+            .stream
+            .write_all(data)
+            .map_err(|e| format!("Can't send to RTT channel {}: {}", channel, e).into())
+    }
+
+    fn start_server(
+        tcl: &mut TclClient,
+        channel: u32,
+        servers: &[Server],
+    ) -> Result<Server, ErrorMsg> {
+        let port = (BASE_PORT..BASE_PORT + SEARCH_RANGE)
+            .find(|port| !servers.iter().any(|s| s.port == *port) && ports::is_free(*port))
+            .ok_or_else(|| ErrorMsg::from("No free port for RTT server!"))?;
+
+        tcl.execute_checked(&format!("rtt server start {} {}", port, channel))?;
+
+        let stream = TcpStream::connect(("127.0.0.1", port))
+            .map_err(|e| format!("Can't connect to RTT server of channel {}: {}", channel, e))?;
+
+        Ok(Server {
+            channel,
+            port,
+            stream,
+        })
+    }
+
+    fn read_output(
+        mut stream: TcpStream,
+        channel: u32,
+        elf: Option<Arc<defmt::Elf>>,
+        window: SessionWindow,
+    ) {
+        let mut buffer = [0u8; 1024];
+        let mut pending: Vec<u8> = Vec::new();
+        let mut decoder = elf.as_deref().map(|elf| defmt::Decoder::new(elf, channel));
+
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => {
+                    if let Some(records) = decoder.as_mut().map(|d| d.feed(&buffer[..size])) {
+                        match records {
+                            Ok(records) => records.into_iter().for_each(|r| window.send(r)),
+                            Err(e) => {
+                                // Raw output is still emitted
+                                error!("{}", e.message);
+                                Notification::error(window.message(&e.message))
+                                    .send_to(&window.window);
+                                decoder = None;
+                            }
+                        }
+                    }
+
+                    pending.extend_from_slice(&buffer[..size]);
+                    let data = take_text(&mut pending);
+
+                    if !data.is_empty() {
+                        window.send(RttOutput { channel, data });
+                    }
+                }
+                Err(e) => {
+                    error!("RTT channel {} read failed: {}", channel, e);
+                    break;
+                }
+            }
+        }
+
+        info!("RTT channel {} closed.", channel);
+    }
+}
+
+impl Drop for Rtt {
+    fn drop(&mut self) {
+        for server in &self.servers {
+            server.stream.shutdown(Shutdown::Both).ok();
+        }
+
+        // OpenOCD may be already stopped, so errors are only logged
+        let stopped = TclClient::connect(self.tcl_port).and_then(|mut tcl| {
+            for server in &self.servers {
+                tcl.execute_checked(&format!("rtt server stop {}", server.port))?;
+            }
+            tcl.execute_checked("rtt stop")
+        });
+
+        match stopped {
+            Ok(_) => info!("RTT stopped."),
+            Err(e) => warn!("RTT was not stopped cleanly: {}", e.message),
+        }
+    }
+}
+
+/// Select up-channels with `defmt` frames
+fn defmt_channels(up: &[Channel]) -> Vec<u32> {
+    let named: Vec<u32> = up
+        .iter()
+        .filter(|channel| channel.name.eq_ignore_ascii_case(DEFMT_CHANNEL))
+        .map(|channel| channel.index)
+        .collect();
+
+    if !named.is_empty() {
+        named
+    } else if up.iter().any(|channel| channel.index == 0) {
+        vec![0]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Take decoded text from the received bytes
+///
+/// Incomplete UTF-8 sequence at the end is kept for the next read, invalid
+/// sequences are replaced.
+pub fn take_text(pending: &mut Vec<u8>) -> String {
+    let complete = match std::str::from_utf8(pending) {
+        Ok(_) => pending.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => pending.len(),
+    };
+
+    let rest = pending.split_off(complete);
+    let text = String::from_utf8_lossy(pending).to_string();
+    *pending = rest;
+    text
+}
+
+/// Parse the output of `rtt channels`
+///
+/// ```text
+/// Channels: up=1, down=1
+/// Up-channels:
+/// 0: Terminal 1024 0
+/// Down-channels:
+/// 0: Terminal 16 0
+/// ```
+fn parse_channels(output: &str) -> Channels {
+    lazy_static! {
+        static ref CHANNEL: Regex = Regex::new(r"^(\d+): (.*) (\d+) (\d+)$").unwrap();
+    }
+
+    let mut channels = Channels::default();
+    let mut is_down = false;
+
+    for line in output.lines().map(str::trim) {
+        if line.starts_with("Up-channels") {
+            is_down = false;
+        } else if line.starts_with("Down-channels") {
+            is_down = true;
+        } else if let Some(caps) = CHANNEL.captures(line) {
+            let channel = Channel {
+                index: caps[1].parse().unwrap_or_default(),
+                name: caps[2].to_string(),
+                size: caps[3].parse().unwrap_or_default(),
+                flags: caps[4].parse().unwrap_or_default(),
+            };
+
+            if is_down {
+                channels.down.push(channel);
+            } else {
+                channels.up.push(channel);
+            }
+        }
+    }
+
+    channels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{defmt_channels, parse_channels, take_text, Channel, Channels};
+
+    fn channel(index: u32, name: &str, size: u32, flags: u32) -> Channel {
+        Channel {
+            index,
+            name: name.into(),
+            size,
+            flags,
+        }
+    }
+
+    #[test]
+    fn channels_of_segger_rtt() {
+        // `rtt channels` output in the format of OpenOCD 0.11 for the
+        // default SEGGER RTT setup
+        let output = "Channels: up=3, down=3\n\
+                      Up-channels:\n\
+                      0: Terminal 1024 0\n\
+                      Down-channels:\n\
+                      0: Terminal 16 0\n";
+
+        assert_eq!(
+            parse_channels(output),
+            Channels {
+                up: vec![channel(0, "Terminal", 1024, 0)],
+                down: vec![channel(0, "Terminal", 16, 0)],
+                defmt: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn channels_with_odd_names() {
+        // Channels without buffers are not listed by OpenOCD, names may be
+        // empty or have spaces
+        let output = "Channels: up=4, down=2\n\
+                      Up-channels:\n\
+                      0: Terminal 1024 2\n\
+                      1: defmt 2048 2\n\
+                      3:  512 0\n\
+                      Down-channels:\n\
+                      1: Debug Input 64 0\n";
+
+        let channels = parse_channels(output);
+        assert_eq!(
+            channels.up,
+            vec![
+                channel(0, "Terminal", 1024, 2),
+                channel(1, "defmt", 2048, 2),
+                channel(3, "", 512, 0),
+            ]
+        );
+        assert_eq!(channels.down, vec![channel(1, "Debug Input", 64, 0)]);
+        assert_eq!(defmt_channels(&channels.up), vec![1]);
+    }
+
+    #[test]
+    fn channels_not_found() {
+        assert_eq!(parse_channels(""), Channels::default());
+        assert_eq!(
+            parse_channels("Channels: up=0, down=0\nUp-channels:\nDown-channels:\n"),
+            Channels::default()
+        );
+    }
+
+    #[test]
+    fn defmt_channel_zero_by_default() {
+        assert_eq!(defmt_channels(&[channel(0, "Terminal", 1024, 0)]), vec![0]);
+        assert!(defmt_channels(&[channel(2, "Terminal", 1024, 0)]).is_empty());
+    }
+
+    #[test]
+    fn text_split_across_reads() {
+        // "Temp: 25°C\n" with `°` split between two reads
+        let mut pending = b"Temp: 25\xc2".to_vec();
+        assert_eq!(take_text(&mut pending), "Temp: 25");
+        assert_eq!(pending, b"\xc2");
+
+        pending.extend_from_slice(b"\xb0C\n");
+        assert_eq!(take_text(&mut pending), "°C\n");
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn invalid_text_is_replaced() {
+        let mut pending = b"a\xffb".to_vec();
+        assert_eq!(take_text(&mut pending), "a\u{fffd}b");
+        assert!(pending.is_empty());
+
+        let mut pending = Vec::new();
+        assert_eq!(take_text(&mut pending), "");
+    }
+}
@@ -0,0 +1,697 @@
+//! Lightweight scanner of OpenOCD cfg scripts
+//!
+//! Scripts are not evaluated. The scanner splits them into TCL commands and
+//! words, follows `source [find ...]` includes and collects the commands
+//! which describe the hardware. Variables set by literal values are
+//! substituted, `if {[info exists VAR]}` is decided by the known variables,
+//! bodies of other conditions are scanned all.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::ErrorMsg;
+use crate::openocd::config::Config;
+use crate::openocd::paths::OpenocdPaths;
+
+/// Maximum depth of nested includes
+const DEPTH_LIMIT: usize = 16;
+
+lazy_static! {
+    static ref FIND: Regex = Regex::new(r"^\[\s*find\s+(\S+)\s*\]$").unwrap();
+    static ref INFO_EXISTS: Regex =
+        Regex::new(r"^\s*(!)?\s*\[\s*info\s+exists\s+:*(\w+)\s*\]\s*$").unwrap();
+    static ref VARIABLE: Regex = Regex::new(r"\$(?:\{(\w+)\}|:*(\w+))").unwrap();
+}
+
+/// Target declared with `target create`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TargetDecl {
+    pub name: String,
+    /// Type of the target, e.g. `cortex_m` or `riscv`
+    pub kind: String,
+}
+
+/// Flash bank declared with `flash bank`
+///
+/// Values are kept as written if they are not literals.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FlashBankDecl {
+    pub name: String,
+    pub driver: String,
+    pub base: String,
+    pub size: String,
+}
+
+/// Hardware described by a cfg file
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ConfigFacts {
+    /// `adapter driver` or legacy `interface`
+    pub adapter_driver: Option<String>,
+    /// Transports selected with `transport select`
+    pub transports: Vec<String>,
+    /// Value of `CHIPNAME` or `_CHIPNAME`
+    pub chipname: Option<String>,
+    pub targets: Vec<TargetDecl>,
+    pub flash_banks: Vec<FlashBankDecl>,
+}
+
+impl ConfigFacts {
+    /// Add facts of an included file, own facts take precedence
+    fn merge(&mut self, other: &ConfigFacts) {
+        if self.adapter_driver.is_none() {
+            self.adapter_driver = other.adapter_driver.clone();
+        }
+        if self.chipname.is_none() {
+            self.chipname = other.chipname.clone();
+        }
+        self.transports.extend(other.transports.iter().cloned());
+        self.targets.extend(other.targets.iter().cloned());
+        self.flash_banks.extend(other.flash_banks.iter().cloned());
+    }
+}
+
+/// cfg file with its facts and the files it includes
+#[derive(Clone, Debug, Serialize)]
+pub struct ConfigTree {
+    pub path: String,
+    pub facts: ConfigFacts,
+    pub includes: Vec<ConfigTree>,
+    /// Includes which were not found, e.g. with unknown variables in paths
+    pub missing: Vec<String>,
+}
+
+impl ConfigTree {
+    /// Return facts of the file merged with facts of all its includes
+    pub fn merged(&self) -> ConfigFacts {
+        let mut facts = self.facts.clone();
+        for include in &self.includes {
+            facts.merge(&include.merged());
+        }
+        facts
+    }
+
+    /// Return all included files, depth first
+    pub fn included(&self) -> Vec<&ConfigTree> {
+        self.includes
+            .iter()
+            .flat_map(|include| {
+                let mut included = vec![include];
+                included.extend(include.included());
+                included
+            })
+            .collect()
+    }
+}
+
+/// Include tree of a config with facts of the whole tree
+#[derive(Clone, Debug, Serialize)]
+pub struct ConfigScan {
+    pub tree: ConfigTree,
+    /// Facts of the config merged with facts of its includes
+    pub facts: ConfigFacts,
+}
+
+/// Word of a TCL command
+struct Word {
+    text: String,
+    /// Word was enclosed in braces, so it is taken literally
+    braced: bool,
+}
+
+/// Scan a config and the files it includes
+pub fn scan(config: &Config) -> Result<ConfigScan, ErrorMsg> {
+    let paths = OpenocdPaths::new()?;
+    let tree = scan_file(Path::new(&config.path), &paths.scripts)?;
+
+    Ok(ConfigScan {
+        facts: tree.merged(),
+        tree,
+    })
+}
+
+/// Scan a cfg file resolving `find` in the `scripts` directory
+pub fn scan_file(path: &Path, scripts: &Path) -> Result<ConfigTree, ErrorMsg> {
+    let mut scanner = Scanner {
+        scripts,
+        vars: HashMap::new(),
+        stack: Vec::new(),
+    };
+    scanner.file(path)
+}
+
+struct Scanner<'a> {
+    scripts: &'a Path,
+    /// Variables shared between files, as OpenOCD does
+    vars: HashMap<String, String>,
+    /// Files being scanned, to break include cycles
+    stack: Vec<PathBuf>,
+}
+
+impl<'a> Scanner<'a> {
+    fn file(&mut self, path: &Path) -> Result<ConfigTree, ErrorMsg> {
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("Can't read config \"{}\": {}", path.display(), e))?;
+
+        let mut tree = ConfigTree {
+            path: path.display().to_string(),
+            facts: ConfigFacts::default(),
+            includes: Vec::new(),
+            missing: Vec::new(),
+        };
+
+        self.stack.push(path.to_path_buf());
+        self.script(&content, &mut tree);
+        self.stack.pop();
+
+        Ok(tree)
+    }
+
+    fn script(&mut self, script: &str, tree: &mut ConfigTree) {
+        for command in split_commands(script) {
+            let words = split_words(&command);
+            if !words.is_empty() {
+                self.command(&words, tree);
+            }
+        }
+    }
+
+    fn command(&mut self, words: &[Word], tree: &mut ConfigTree) {
+        let arg = |index: usize| words.get(index).map(|word| word.text.as_str());
+        let facts = &mut tree.facts;
+
+        match (arg(0), arg(1)) {
+            (Some("source"), Some(_)) => {
+                let path = self.value(&words[words.len() - 1]);
+                self.include(&path, tree);
+            }
+            (Some("set"), Some(name)) if words.len() == 3 => {
+                let name = name.trim_start_matches(':').to_string();
+                let value = self.value(&words[2]);
+
+                // Values of other commands and unknown variables are not tracked
+                if !value.contains('$') && !value.contains('[') {
+                    if name == "CHIPNAME" || name == "_CHIPNAME" {
+                        facts.chipname = Some(value.clone());
+                    }
+                    self.vars.insert(name, value);
+                }
+            }
+            (Some("adapter"), Some("driver")) | (Some("interface"), Some(_)) => {
+                if let Some(word) = words.last() {
+                    facts.adapter_driver = Some(self.value(word));
+                }
+            }
+            (Some("transport"), Some("select")) if words.len() > 2 => {
+                facts.transports.push(self.value(&words[2]));
+            }
+            (Some("target"), Some("create")) if words.len() > 3 => {
+                facts.targets.push(TargetDecl {
+                    name: self.value(&words[2]),
+                    kind: self.value(&words[3]),
+                });
+            }
+            (Some("flash"), Some("bank")) if words.len() > 5 => {
+                facts.flash_banks.push(FlashBankDecl {
+                    name: self.value(&words[2]),
+                    driver: self.value(&words[3]),
+                    base: self.value(&words[4]),
+                    size: self.value(&words[5]),
+                });
+            }
+            (Some("if"), Some(_)) => self.condition(words, tree),
+            _ => {}
+        }
+    }
+
+    /// Scan bodies of `if`, only the taken one if the condition is known
+    fn condition(&mut self, words: &[Word], tree: &mut ConfigTree) {
+        let mut branches: Vec<(Option<&Word>, &Word)> = Vec::new();
+        let mut index = 0;
+
+        while index < words.len() {
+            match words[index].text.as_str() {
+                "if" | "elseif" if index + 2 < words.len() => {
+                    // Optional `then` goes before the body
+                    let skip = match words[index + 2].text.as_str() {
+                        "then" if index + 3 < words.len() => 1,
+                        _ => 0,
+                    };
+                    branches.push((Some(&words[index + 1]), &words[index + 2 + skip]));
+                    index += 3 + skip;
+                }
+                "else" if index + 1 < words.len() => {
+                    branches.push((None, &words[index + 1]));
+                    index += 2;
+                }
+                _ => break,
+            }
+        }
+
+        let mut scanned = Vec::new();
+        for (condition, body) in branches {
+            match condition.map(|condition| self.decide(condition)) {
+                // Unknown condition, the body may be taken
+                Some(None) => scanned.push(body),
+                Some(Some(false)) => {}
+                Some(Some(true)) | None => {
+                    scanned.push(body);
+                    break;
+                }
+            }
+        }
+
+        for body in scanned.into_iter().filter(|body| body.braced) {
+            self.script(&body.text, tree);
+        }
+    }
+
+    /// Decide `[info exists VAR]` conditions, `None` for the others
+    fn decide(&self, condition: &Word) -> Option<bool> {
+        let caps = INFO_EXISTS.captures(&condition.text)?;
+        let exists = self.vars.contains_key(&caps[2]);
+        Some(exists != caps.get(1).is_some())
+    }
+
+    fn include(&mut self, path: &str, tree: &mut ConfigTree) {
+        let found = match FIND.captures(path) {
+            Some(caps) => Some(self.scripts.join(&caps[1])),
+            None if !path.contains('[') => {
+                let path = PathBuf::from(path);
+                Some(if path.is_absolute() {
+                    path
+                } else {
+                    self.scripts.join(path)
+                })
+            }
+            None => None,
+        };
+
+        let found = match found {
+            Some(found) if !found.to_string_lossy().contains('$') && found.is_file() => found,
+            _ => {
+                tree.missing.push(path.to_string());
+                return;
+            }
+        };
+
+        if self.stack.contains(&found) || self.stack.len() >= DEPTH_LIMIT {
+            tree.missing.push(path.to_string());
+            return;
+        }
+
+        match self.file(&found) {
+            Ok(include) => tree.includes.push(include),
+            Err(_) => tree.missing.push(path.to_string()),
+        }
+    }
+
+    /// Return the word with known variables substituted
+    fn value(&self, word: &Word) -> String {
+        if word.braced {
+            return word.text.clone();
+        }
+
+        VARIABLE
+            .replace_all(&word.text, |caps: &regex::Captures| {
+                let name = caps.get(1).or_else(|| caps.get(2)).unwrap().as_str();
+                self.vars
+                    .get(name)
+                    .cloned()
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .to_string()
+    }
+}
+
+/// Split a script into commands
+///
+/// Commands are separated by newlines and `;` outside of braces, brackets
+/// and quotes. Comments and line continuations are dropped.
+fn split_commands(script: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut command = String::new();
+    let mut braces = 0usize;
+    let mut brackets = 0usize;
+    let mut quoted = false;
+    let mut chars = script.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let at_top = braces == 0 && brackets == 0 && !quoted;
+
+        match c {
+            '\\' => match chars.next() {
+                Some('\n') => command.push(' '),
+                Some(next) => {
+                    command.push(c);
+                    command.push(next);
+                }
+                None => {}
+            },
+            '#' if at_top && command.trim().is_empty() => {
+                // Comment lasts to the end of the line, continuations included
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '\n' => break,
+                        _ => {}
+                    }
+                }
+                command.clear();
+            }
+            '\n' | ';' if at_top => {
+                commands.push(std::mem::take(&mut command));
+            }
+            _ => {
+                match c {
+                    '{' if !quoted => braces += 1,
+                    '}' if !quoted => braces = braces.saturating_sub(1),
+                    '[' if braces == 0 => brackets += 1,
+                    ']' if braces == 0 => brackets = brackets.saturating_sub(1),
+                    '"' if braces == 0 && brackets == 0 => quoted = !quoted,
+                    _ => {}
+                }
+                command.push(c);
+            }
+        }
+    }
+    commands.push(command);
+
+    commands
+        .into_iter()
+        .map(|command| command.trim().to_string())
+        .filter(|command| !command.is_empty())
+        .collect()
+}
+
+/// Split a command into words
+///
+/// Braces and quotes around words are removed, brackets are kept.
+fn split_words(command: &str) -> Vec<Word> {
+    let mut words = Vec::new();
+    let mut chars = command.chars().peekable();
+
+    loop {
+        while matches!(chars.peek(), Some(c) if c.is_whitespace()) {
+            chars.next();
+        }
+
+        let first = match chars.next() {
+            Some(first) => first,
+            None => break,
+        };
+
+        let mut text = String::new();
+        let braced = first == '{';
+
+        match first {
+            '{' => {
+                let mut depth = 1;
+                for c in chars.by_ref() {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                    text.push(c);
+                }
+            }
+            '"' => {
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => text.extend(chars.next()),
+                        '"' => break,
+                        _ => text.push(c),
+                    }
+                }
+            }
+            _ => {
+                let mut brackets = 0usize;
+                let mut c = first;
+                loop {
+                    match c {
+                        '[' => brackets += 1,
+                        ']' => brackets = brackets.saturating_sub(1),
+                        _ => {}
+                    }
+                    text.push(c);
+
+                    match chars.peek() {
+                        Some(next) if next.is_whitespace() && brackets == 0 => break,
+                        Some(_) => c = chars.next().unwrap(),
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        words.push(Word { text, braced });
+    }
+
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{scan_file, split_commands, split_words, ConfigTree, TargetDecl};
+    use crate::openocd::testing::TempDir;
+
+    fn words(command: &str) -> Vec<(String, bool)> {
+        split_words(command)
+            .into_iter()
+            .map(|word| (word.text, word.braced))
+            .collect()
+    }
+
+    fn scan(scripts: &TempDir, path: &str) -> ConfigTree {
+        scan_file(&scripts.path().join(path), scripts.path()).unwrap()
+    }
+
+    #[test]
+    fn commands_and_comments() {
+        let script = "# Interface of the board\n\
+                      adapter driver hla\n\
+                      \n\
+                      # Continued comment \\\n\
+                      still comment\n\
+                      transport select hla_swd ;# trailing comment\n";
+
+        assert_eq!(
+            split_commands(script),
+            vec!["adapter driver hla", "transport select hla_swd"]
+        );
+    }
+
+    #[test]
+    fn line_continuation() {
+        let script = "flash bank $_FLASHNAME stm32f1x 0x08000000 0 \\\n    0 0 $_TARGETNAME\n";
+
+        assert_eq!(
+            split_commands(script),
+            vec!["flash bank $_FLASHNAME stm32f1x 0x08000000 0      0 0 $_TARGETNAME"]
+        );
+    }
+
+    #[test]
+    fn semicolons() {
+        assert_eq!(
+            split_commands("set A 1; set B 2;set C 3"),
+            vec!["set A 1", "set B 2", "set C 3"]
+        );
+    }
+
+    #[test]
+    fn nested_braces_and_brackets() {
+        let script = "if {[info exists CHIPNAME]} {\n\
+                      \x20   set _CHIPNAME {a {b; c}}\n\
+                      }\n\
+                      set _SIZE [expr {1 +\n2}]; set D 4\n";
+
+        assert_eq!(
+            split_commands(script),
+            vec![
+                "if {[info exists CHIPNAME]} {\n    set _CHIPNAME {a {b; c}}\n}",
+                "set _SIZE [expr {1 +\n2}]",
+                "set D 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            split_commands("echo \"a; b\n# c\"\nset A 1"),
+            vec!["echo \"a; b\n# c\"", "set A 1"]
+        );
+    }
+
+    #[test]
+    fn words_of_command() {
+        assert_eq!(
+            words("target create $_TARGETNAME cortex_m -endian $_ENDIAN"),
+            vec![
+                ("target".to_string(), false),
+                ("create".to_string(), false),
+                ("$_TARGETNAME".to_string(), false),
+                ("cortex_m".to_string(), false),
+                ("-endian".to_string(), false),
+                ("$_ENDIAN".to_string(), false),
+            ]
+        );
+    }
+
+    #[test]
+    fn words_in_braces_brackets_and_quotes() {
+        assert_eq!(
+            words("source [find target/stm32f1x.cfg]"),
+            vec![
+                ("source".to_string(), false),
+                ("[find target/stm32f1x.cfg]".to_string(), false),
+            ]
+        );
+        assert_eq!(
+            words("set A {a {b c}}"),
+            vec![
+                ("set".to_string(), false),
+                ("A".to_string(), false),
+                ("a {b c}".to_string(), true),
+            ]
+        );
+        assert_eq!(
+            words(r#"echo "a \"b\" c""#),
+            vec![
+                ("echo".to_string(), false),
+                ("a \"b\" c".to_string(), false)
+            ]
+        );
+    }
+
+    #[test]
+    fn info_exists_branch() {
+        let scripts = TempDir::new("script-branch");
+        scripts.write(
+            "target/chip.cfg",
+            "if { [info exists CHIPNAME] } {\n\
+             \x20   set _CHIPNAME $CHIPNAME\n\
+             } else {\n\
+             \x20   set _CHIPNAME stm32f1x\n\
+             }\n\
+             if {![info exists WORKAREASIZE]} {\n\
+             \x20   transport select swd\n\
+             }\n",
+        );
+        scripts.write(
+            "board/named.cfg",
+            "set CHIPNAME stm32f103c8\nset WORKAREASIZE 0x1000\nsource [find target/chip.cfg]\n",
+        );
+        scripts.write("board/default.cfg", "source [find target/chip.cfg]\n");
+
+        let named = scan(&scripts, "board/named.cfg");
+        let chip = &named.includes[0].facts;
+        assert_eq!(chip.chipname.as_deref(), Some("stm32f103c8"));
+        assert!(chip.transports.is_empty());
+
+        let default = scan(&scripts, "board/default.cfg");
+        let chip = &default.includes[0].facts;
+        assert_eq!(chip.chipname.as_deref(), Some("stm32f1x"));
+        assert_eq!(chip.transports, vec!["swd"]);
+    }
+
+    #[test]
+    fn unknown_condition_scans_all_branches() {
+        let scripts = TempDir::new("script-unknown");
+        scripts.write(
+            "target/chip.cfg",
+            "if {$_ENABLE_JTAG > 0} {\n\
+             \x20   transport select jtag\n\
+             } else {\n\
+             \x20   transport select swd\n\
+             }\n",
+        );
+
+        let tree = scan(&scripts, "target/chip.cfg");
+        assert_eq!(tree.facts.transports, vec!["jtag", "swd"]);
+    }
+
+    #[test]
+    fn variables() {
+        let scripts = TempDir::new("script-vars");
+        scripts.write(
+            "target/chip.cfg",
+            "set _CHIPNAME stm32f1x\n\
+             set _TARGETNAME $_CHIPNAME.cpu\n\
+             set _FLASHSIZE [expr {64 * 1024}]\n\
+             target create $_TARGETNAME cortex_m -dap ${_CHIPNAME}.dap\n\
+             flash bank ${_CHIPNAME}.flash stm32f1x 0x08000000 $_FLASHSIZE 0 0 $_TARGETNAME\n",
+        );
+
+        let facts = scan(&scripts, "target/chip.cfg").facts;
+        assert_eq!(facts.chipname.as_deref(), Some("stm32f1x"));
+        assert_eq!(
+            facts.targets,
+            vec![TargetDecl {
+                name: "stm32f1x.cpu".into(),
+                kind: "cortex_m".into(),
+            }]
+        );
+
+        let bank = &facts.flash_banks[0];
+        assert_eq!(bank.name, "stm32f1x.flash");
+        assert_eq!(bank.driver, "stm32f1x");
+        assert_eq!(bank.base, "0x08000000");
+        // Results of commands are not evaluated
+        assert_eq!(bank.size, "$_FLASHSIZE");
+    }
+
+    #[test]
+    fn include_cycle() {
+        let scripts = TempDir::new("script-cycle");
+        scripts.write("a.cfg", "adapter driver cmsis-dap\nsource [find b.cfg]\n");
+        scripts.write("b.cfg", "transport select swd\nsource [find a.cfg]\n");
+
+        let tree = scan(&scripts, "a.cfg");
+        assert_eq!(tree.includes.len(), 1);
+
+        let b = &tree.includes[0];
+        assert!(b.includes.is_empty());
+        assert_eq!(b.missing, vec!["[find a.cfg]"]);
+
+        let facts = tree.merged();
+        assert_eq!(facts.adapter_driver.as_deref(), Some("cmsis-dap"));
+        assert_eq!(facts.transports, vec!["swd"]);
+    }
+
+    #[test]
+    fn missing_includes() {
+        let scripts = TempDir::new("script-missing");
+        scripts.write("interface/stlink.cfg", "adapter driver hla\n");
+        scripts.write(
+            "board/board.cfg",
+            "source [find interface/stlink.cfg]\n\
+             source [find interface/none.cfg]\n\
+             source [find target/$CHIP.cfg]\n",
+        );
+
+        let tree = scan(&scripts, "board/board.cfg");
+        assert_eq!(tree.included().len(), 1);
+        assert_eq!(
+            tree.missing,
+            vec!["[find interface/none.cfg]", "[find target/$CHIP.cfg]"]
+        );
+        assert_eq!(tree.merged().adapter_driver.as_deref(), Some("hla"));
+    }
+
+    #[test]
+    fn missing_config() {
+        let scripts = TempDir::new("script-none");
+        assert!(scan_file(&scripts.path().join("none.cfg"), scripts.path()).is_err());
+    }
+}
@@ -0,0 +1,531 @@
+use command_group::GroupChild;
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::{BufRead, BufReader, Read};
+use std::{
+    process::ChildStdout,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+use tauri::Window;
+use threadpool::ThreadPool;
+
+use crate::api::TauriEvent;
+use crate::{
+    error::ErrorMsg,
+    gdb::Gdb,
+    notification::Notification,
+    openocd,
+    openocd::config::Config,
+    openocd::console::Console,
+    openocd::events::{
+        Event, Initiator, Kind, SemihostingOutput, ShutdownProgress, ShutdownStep, StopReport,
+        TargetState,
+    },
+    openocd::flash::{ProgressTracker, SharedTracker},
+    openocd::log::Record,
+    openocd::ports::Ports,
+    openocd::proc::LaunchOptions,
+    openocd::rpc::TclClient,
+    openocd::rtt::Rtt,
+    openocd::swo::Swo,
+    openocd::target::{States, TargetMonitor},
+};
+
+/// Slot with a handler of the running OpenOCD process
+type ProcSlot = Arc<Mutex<Option<Arc<Mutex<GroupChild>>>>>;
+
+/// Connections and processes which work with the running OpenOCD
+///
+/// They are dropped as soon as OpenOCD exits, by a shutdown or by itself,
+/// so GDB doesn't outlive the session and stop commands of RTT and SWO are
+/// not sent to the next OpenOCD listening on the same port.
+#[derive(Default)]
+pub struct Attached {
+    pub console: Option<Console>,
+    pub rtt: Option<Rtt>,
+    pub swo: Option<Swo>,
+    pub gdb: Option<Gdb>,
+}
+
+impl Attached {
+    fn clear(&mut self) {
+        self.console.take();
+        self.rtt.take();
+        self.swo.take();
+        self.gdb.take();
+    }
+}
+
+/// Attached handles shared with commands which attach them without the
+/// `App` lock
+pub type SharedAttached = Arc<Mutex<Attached>>;
+
+/// ID of the session controlled by commands without an explicit session
+pub const DEFAULT_ID: &str = "default";
+
+/// Count of last OpenOCD error lines reported on stop
+const ERRORS_LIMIT: usize = 5;
+
+/// Window wrapper which emits events to the topics of a session
+///
+/// Events of the default session are emitted to the plain topics, e.g.
+/// `app://openocd/output`. Events of the other sessions are emitted to the
+/// topics suffixed with the session ID, e.g. `app://openocd/output/<id>`.
+#[derive(Clone)]
+pub struct SessionWindow {
+    pub window: Window,
+    pub id: String,
+}
+
+impl SessionWindow {
+    pub fn send<E>(&self, event: E)
+    where
+        E: TauriEvent + Serialize + Clone,
+    {
+        if self.id == DEFAULT_ID {
+            event.send_to(&self.window)
+        } else {
+            event.send_scoped_to(&self.window, &self.id)
+        }
+    }
+
+    /// Prefix a notification message with the session ID if it is not the default one
+    pub fn message(&self, msg: &str) -> String {
+        if self.id == DEFAULT_ID {
+            msg.into()
+        } else {
+            format!("[{}] {}", self.id, msg)
+        }
+    }
+}
+
+/// Short description of a session returned to the frontend
+#[derive(Serialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub configs: Vec<Config>,
+    pub options: LaunchOptions,
+    pub ports: Ports,
+    pub running: bool,
+}
+
+/// Result of a session start
+#[derive(Serialize)]
+pub struct Started {
+    pub message: String,
+    /// Ports OpenOCD was started with
+    pub ports: Ports,
+}
+
+/// OpenOCD process started with its own configs and ports
+pub struct Session {
+    pub id: String,
+    pub configs: Vec<Config>,
+    pub options: LaunchOptions,
+    pub ports: Ports,
+    attached: SharedAttached,
+    /// Run states of targets tracked while OpenOCD is running
+    targets: States,
+    /// Programming progress tracked in OpenOCD output
+    progress: SharedTracker,
+    workers: ThreadPool,
+    proc: ProcSlot,
+    stop_requested: Arc<AtomicBool>,
+}
+
+impl Session {
+    pub fn new(id: String, ports: Ports) -> Self {
+        Session {
+            id,
+            configs: Vec::new(),
+            options: LaunchOptions::default(),
+            ports,
+            attached: Arc::new(Mutex::new(Attached::default())),
+            targets: Arc::new(Mutex::new(Vec::new())),
+            progress: Arc::new(Mutex::new(ProgressTracker::default())),
+            workers: ThreadPool::new(1),
+            proc: Arc::new(Mutex::new(None)),
+            stop_requested: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Check a session ID can be used as a part of event topics
+    pub fn validate_id(id: &str) -> Result<(), ErrorMsg> {
+        let is_valid = !id.is_empty()
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if is_valid {
+            Ok(())
+        } else {
+            Err(format!(
+                r#"Bad session ID "{}": only latin letters, digits, "-" and "_" are allowed!"#,
+                id
+            )
+            .into())
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.workers.active_count() > 0
+    }
+
+    /// Return an error if OpenOCD process of the session is not alive
+    pub fn ensure_started(&self) -> Result<(), ErrorMsg> {
+        if self.proc.lock().unwrap().is_some() {
+            Ok(())
+        } else {
+            Err(format!(r#"OpenOCD of session "{}" is not started!"#, self.id).into())
+        }
+    }
+
+    /// Return last known run states of targets
+    pub fn target_states(&self) -> Vec<TargetState> {
+        self.targets.lock().unwrap().clone()
+    }
+
+    /// Return connections and processes attached to the running OpenOCD
+    pub fn attached(&self) -> MutexGuard<'_, Attached> {
+        self.attached.lock().unwrap()
+    }
+
+    /// Return the handles to attach connections which talk to OpenOCD while
+    /// they are started or stopped
+    pub fn shared_attached(&self) -> SharedAttached {
+        self.attached.clone()
+    }
+
+    /// Return the tracker of programming progress in OpenOCD output
+    pub fn flash_progress(&self) -> SharedTracker {
+        self.progress.clone()
+    }
+
+    pub fn info(&self) -> SessionInfo {
+        SessionInfo {
+            id: self.id.clone(),
+            configs: self.configs.clone(),
+            options: self.options.clone(),
+            ports: self.ports,
+            running: self.is_running(),
+        }
+    }
+
+    /// Start OpenOCD of the session with provided configs and options
+    ///
+    /// Ports of the session are checked before start. Busy ports are replaced
+    /// with free ones out of `reserved` ports of other sessions if
+    /// `auto_ports` option is set, otherwise an error with owners of busy
+    /// ports is returned. Return error if the session is already running.
+    pub fn start(
+        &mut self,
+        configs: Vec<Config>,
+        options: LaunchOptions,
+        reserved: &[Ports],
+        window: Window,
+    ) -> Result<Started, ErrorMsg> {
+        options.validate()?;
+
+        if self.is_running() {
+            return Err(format!(
+                r#"OpenOCD of session "{}" has been already started!"#,
+                self.id
+            )
+            .into());
+        }
+
+        let busy = self.ports.busy();
+        if !busy.is_empty() {
+            if options.auto_ports {
+                self.ports = self.ports.reassign(reserved)?;
+                info!("OpenOCD ports of session \"{}\": {:?}", self.id, self.ports);
+            } else {
+                let reasons: Vec<String> = busy.iter().map(|port| port.describe()).collect();
+                return Err(format!("OpenOCD can't be started: {}!", reasons.join(", ")).into());
+            }
+        }
+
+        let window = SessionWindow {
+            window,
+            id: self.id.clone(),
+        };
+
+        // Attached to the previous OpenOCD if it has exited while they were
+        // being attached
+        self.attached().clear();
+
+        self.configs = configs;
+        self.options = options;
+        self.start_openocd(window);
+
+        Ok(Started {
+            message: "OpenOCD started!".into(),
+            ports: self.ports,
+        })
+    }
+
+    /// Prepare a shutdown of OpenOCD process of the session
+    ///
+    /// The shutdown itself is done by `Shutdown::run`, so it can be run
+    /// without holding the `App` lock. Stop event with an exit status is
+    /// emitted after the process is reaped.
+    pub fn shutdown(&mut self, timeout: Duration, window: Window) -> Result<Shutdown, ErrorMsg> {
+        self.ensure_started()?;
+
+        self.stop_requested.store(true, Ordering::SeqCst);
+        self.attached().clear();
+
+        Ok(Shutdown {
+            proc: self.proc.clone(),
+            tcl_port: self.ports.tcl,
+            timeout,
+            window: SessionWindow {
+                window,
+                id: self.id.clone(),
+            },
+        })
+    }
+
+    fn start_openocd(&self, window: SessionWindow) {
+        let configs = self.configs.clone();
+        let options = self.options.clone();
+        let ports = self.ports;
+        let openocd_proc = self.proc.clone();
+        let stop_requested = self.stop_requested.clone();
+        let targets = self.targets.clone();
+        let progress = self.progress.clone();
+        let attached = self.attached.clone();
+
+        self.workers.execute(move || {
+            let command = openocd::proc::start(&configs, &ports, &options);
+
+            if let Some(command) = command {
+                let started_at = Instant::now();
+                let cmd = Arc::new(Mutex::new(command));
+                openocd_proc.lock().unwrap().replace(cmd.clone());
+                stop_requested.store(false, Ordering::SeqCst);
+
+                let stderr = cmd.lock().unwrap().inner().stderr.take().unwrap();
+                if let Some(stdout) = cmd.lock().unwrap().inner().stdout.take() {
+                    let window = window.clone();
+                    thread::spawn(move || Self::read_semihosting(stdout, window));
+                }
+                let reader = BufReader::new(stderr);
+                let mut errors = VecDeque::with_capacity(ERRORS_LIMIT);
+                let mut monitor: Option<TargetMonitor> = None;
+
+                Self::send_event(&window, Event::start(), None);
+
+                reader.lines().map_while(Result::ok).for_each(|line| {
+                    window.send(format!("{}\n", line));
+
+                    let record = Record::parse(&line);
+                    if record.level == openocd::log::Level::Error {
+                        if errors.len() == ERRORS_LIMIT {
+                            errors.pop_front();
+                        }
+                        errors.push_back(record.message.clone());
+                    }
+                    if let Some(progress) = progress.lock().unwrap().track(&record) {
+                        window.send(progress);
+                    }
+                    if let openocd::log::Kind::Listening { service, port } = &record.kind {
+                        if service == "tcl" && monitor.is_none() {
+                            monitor =
+                                Some(TargetMonitor::start(*port, targets.clone(), window.clone()));
+                        }
+                    }
+                    window.send(record);
+
+                    info!("-- [{}] {}", window.id, line);
+                });
+
+                monitor.take();
+                targets.lock().unwrap().clear();
+
+                // Don't hold the lock while waiting, so the process can be
+                // signaled by `Shutdown` if it has closed stderr but still alive.
+                let status = loop {
+                    match cmd.lock().unwrap().try_wait() {
+                        Ok(Some(status)) => break Ok(status),
+                        Ok(None) => thread::sleep(Shutdown::POLL_INTERVAL),
+                        Err(e) => break Err(e),
+                    }
+                };
+                openocd_proc.lock().unwrap().take();
+                attached.lock().unwrap().clear();
+
+                let (exit_code, signal) = match status {
+                    Ok(status) => openocd::proc::exit_reason(status),
+                    Err(e) => {
+                        error!("OpenOCD exit status is unknown: {}", e);
+                        (None, None)
+                    }
+                };
+
+                let initiator = if stop_requested.load(Ordering::SeqCst) {
+                    Initiator::User
+                } else {
+                    Initiator::Process
+                };
+
+                let report = StopReport {
+                    exit_code,
+                    signal,
+                    initiator,
+                    duration_ms: started_at.elapsed().as_millis() as u64,
+                    errors: errors.into_iter().collect(),
+                };
+
+                Self::send_event(&window, Event::stop(Some(report)), None)
+            } else {
+                Self::send_event(&window, Event::stop(None), Some("OpenOCD was not started!"))
+            }
+        });
+    }
+
+    /// Emit the output of the target written through semihosting
+    ///
+    /// OpenOCD diagnostics go to stderr, so stdout has the output only.
+    fn read_semihosting(mut stdout: ChildStdout, window: SessionWindow) {
+        let mut buffer = [0u8; 1024];
+        let mut pending: Vec<u8> = Vec::new();
+
+        loop {
+            match stdout.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => {
+                    pending.extend_from_slice(&buffer[..size]);
+                    let data = openocd::rtt::take_text(&mut pending);
+
+                    if !data.is_empty() {
+                        window.send(SemihostingOutput(data));
+                    }
+                }
+                Err(e) => {
+                    error!("Semihosting output read failed: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    fn send_event(window: &SessionWindow, event: Event, msg: Option<&str>) {
+        match event.kind {
+            Kind::Start => {
+                let msg = window.message(msg.map_or("OpenOCD started!", |s| s));
+                info!("{}", msg);
+                Notification::info(msg).send_to(&window.window);
+            }
+            Kind::Stop => {
+                let msg = match (msg, &event.report) {
+                    (Some(msg), _) => msg.to_string(),
+                    (None, Some(report)) if report.initiator == Initiator::Process => {
+                        match report.errors.last() {
+                            Some(error) => format!("OpenOCD exited: {}", error),
+                            None => "OpenOCD exited!".to_string(),
+                        }
+                    }
+                    (None, _) => "OpenOCD stopped!".to_string(),
+                };
+                let msg = window.message(&msg);
+                warn!("{}", msg);
+                Notification::warn(msg).send_to(&window.window);
+            }
+        }
+        window.send(event);
+    }
+}
+
+/// Escalating shutdown of OpenOCD process
+///
+/// Every next step is taken only if the process is still alive after the
+/// timeout of the previous one:
+///
+/// 1. `shutdown` command is sent over the TCL connection;
+/// 2. SIGTERM is sent to the process group (skipped on Windows);
+/// 3. the process group is killed.
+///
+/// Every step is reported as `app://openocd/shutdown` event of the session.
+pub struct Shutdown {
+    proc: ProcSlot,
+    tcl_port: u16,
+    timeout: Duration,
+    window: SessionWindow,
+}
+
+impl Shutdown {
+    /// Interval of polling for the process exit
+    const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+    pub fn run(self) -> Result<String, ErrorMsg> {
+        self.report(ShutdownStep::Command);
+        let response =
+            TclClient::connect(self.tcl_port).and_then(|mut tcl| tcl.execute("shutdown"));
+        if let Err(e) = response {
+            warn!("OpenOCD shutdown command failed: {}", e.message);
+        }
+        if self.wait_exit() {
+            info!("OpenOCD shut down.");
+            return Ok("OpenOCD stopped".into());
+        }
+
+        if cfg!(unix) {
+            self.report(ShutdownStep::Terminate);
+            if self.with_proc(openocd::proc::terminate_proc) && self.wait_exit() {
+                info!("OpenOCD terminated.");
+                return Ok("OpenOCD stopped".into());
+            }
+        }
+
+        self.report(ShutdownStep::Kill);
+        if self.with_proc(openocd::proc::kill_proc) && self.wait_exit() {
+            info!("OpenOCD killed.");
+            Ok("OpenOCD killed".into())
+        } else {
+            error!("OpenOCD was not killed!");
+            Err("OpenOCD was not killed!".into())
+        }
+    }
+
+    fn report(&self, step: ShutdownStep) {
+        info!("OpenOCD shutdown step: {:?}", step);
+        self.window.send(ShutdownProgress {
+            step,
+            timeout_ms: self.timeout.as_millis() as u64,
+        });
+    }
+
+    /// Call `f` with the process if it is still alive
+    ///
+    /// Return `true` if the process has already exited.
+    fn with_proc(&self, f: fn(&mut GroupChild) -> bool) -> bool {
+        let proc = self.proc.lock().unwrap().clone();
+        match proc {
+            Some(proc) => f(&mut proc.lock().unwrap()),
+            None => true,
+        }
+    }
+
+    /// Wait until the process is reaped or the timeout is expired
+    ///
+    /// Return `true` if the process has exited.
+    fn wait_exit(&self) -> bool {
+        let started_at = Instant::now();
+
+        while started_at.elapsed() < self.timeout {
+            if self.proc.lock().unwrap().is_none() {
+                return true;
+            }
+            thread::sleep(Self::POLL_INTERVAL);
+        }
+
+        self.proc.lock().unwrap().is_none()
+    }
+}
@@ -0,0 +1,209 @@
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+
+use crate::error::ErrorMsg;
+use crate::openocd::{
+    events::SwoOutput,
+    itm::{Decoder, Packet},
+    ports,
+    rpc::TclClient,
+    rtt::take_text,
+    session::SessionWindow,
+    version::Capabilities,
+};
+
+/// First port tried for the TCP sink of the trace
+const BASE_PORT: u16 = 19121;
+
+/// Count of ports tried while searching for a free port of the TCP sink
+const SEARCH_RANGE: u16 = 100;
+
+/// Count of ITM stimulus ports
+const STIMULUS_PORTS: u8 = 32;
+
+/// Clocks of the SWO output of the target
+#[derive(Clone, Debug, Deserialize)]
+pub struct SwoSetup {
+    /// Frequency of the trace clock, usually the core clock
+    pub traceclk_hz: u32,
+    /// Frequency of the SWO pin, selected by the adapter if not provided
+    pub pin_freq_hz: Option<u32>,
+    /// Stimulus ports to enable, all ports if not provided
+    pub ports: Option<Vec<u8>>,
+}
+
+impl SwoSetup {
+    fn validate(&self) -> Result<(), ErrorMsg> {
+        if self.traceclk_hz == 0 {
+            return Err("Trace clock frequency is not set!".into());
+        }
+        if let Some(ports) = &self.ports {
+            if ports.is_empty() {
+                return Err("No ITM stimulus ports selected!".into());
+            }
+            if let Some(port) = ports.iter().find(|port| **port >= STIMULUS_PORTS) {
+                return Err(format!("Bad ITM stimulus port {}: 0-31 are allowed!", port).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Running SWO trace of an OpenOCD session
+///
+/// TPIU of the target is configured to send the trace in UART mode without
+/// the formatter to a TCP sink of OpenOCD. ITM packets of the trace are
+/// decoded and text of every stimulus port is emitted as `app://openocd/swo`
+/// event of the session. TPIU is disabled on drop.
+pub struct Swo {
+    tcl_port: u16,
+    /// Name of the TPIU object, `None` for OpenOCD before 0.12
+    tpiu: Option<String>,
+    stream: TcpStream,
+}
+
+impl Swo {
+    /// Configure TPIU and ITM of the target and start decoding of the trace
+    pub fn start(tcl_port: u16, setup: &SwoSetup, window: SessionWindow) -> Result<Swo, ErrorMsg> {
+        setup.validate()?;
+
+        let port = (BASE_PORT..BASE_PORT + SEARCH_RANGE)
+            .find(|port| ports::is_free(*port))
+            .ok_or_else(|| ErrorMsg::from("No free port for SWO trace!"))?;
+
+        let mut tcl = TclClient::connect(tcl_port)?;
+
+        // OpenOCD 0.12 replaced `tpiu config` with TPIU objects
+        let capabilities = Capabilities::get();
+        let tpiu = if capabilities.probed && !capabilities.tpiu_objects {
+            None
+        } else {
+            tcl.execute_checked("tpiu names")
+                .ok()
+                .and_then(|names| names.split_whitespace().next().map(str::to_string))
+        };
+
+        let pin_freq = setup.pin_freq_hz.map(|freq| freq.to_string());
+        match &tpiu {
+            Some(name) => {
+                let mut command = format!(
+                    "{} configure -protocol uart -output :{} -traceclk {}",
+                    name, port, setup.traceclk_hz
+                );
+                if let Some(freq) = &pin_freq {
+                    command.push_str(&format!(" -pin-freq {}", freq));
+                }
+                tcl.execute_checked(&command)?;
+                tcl.execute_checked(&format!("{} enable", name))?;
+            }
+            None => {
+                tcl.execute_checked(&format!(
+                    "tpiu config internal :{} uart off {} {}",
+                    port,
+                    setup.traceclk_hz,
+                    pin_freq.as_deref().unwrap_or_default()
+                ))?;
+            }
+        }
+
+        let stream = match TcpStream::connect(("127.0.0.1", port)) {
+            Ok(stream) => stream,
+            Err(e) => {
+                Self::disable(tcl_port, &tpiu).ok();
+                return Err(format!("Can't connect to SWO trace sink: {}", e).into());
+            }
+        };
+
+        // TPIU is disabled by drop on error
+        let swo = Swo {
+            tcl_port,
+            tpiu,
+            stream,
+        };
+
+        match &setup.ports {
+            Some(ports) => {
+                for port in ports {
+                    tcl.execute_checked(&format!("itm port {} on", port))?;
+                }
+            }
+            None => {
+                tcl.execute_checked("itm ports on")?;
+            }
+        }
+
+        let reader = swo
+            .stream
+            .try_clone()
+            .map_err(|e| format!("Can't configure SWO connection: {}", e))?;
+        thread::spawn(move || Self::read_trace(reader, window));
+
+        info!("SWO started on port {}.", port);
+        Ok(swo)
+    }
+
+    fn read_trace(mut stream: TcpStream, window: SessionWindow) {
+        let mut buffer = [0u8; 1024];
+        let mut decoder = Decoder::default();
+        let mut pending: HashMap<u8, Vec<u8>> = HashMap::new();
+
+        loop {
+            match stream.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => {
+                    let mut received: Vec<u8> = Vec::new();
+
+                    for packet in decoder.feed(&buffer[..size]) {
+                        match packet {
+                            Packet::Instrumentation { port, payload } => {
+                                pending.entry(port).or_default().extend(payload);
+                                if !received.contains(&port) {
+                                    received.push(port);
+                                }
+                            }
+                            Packet::Overflow => warn!("ITM overflow: trace packets were lost."),
+                            _ => {}
+                        }
+                    }
+
+                    for port in received {
+                        let data = take_text(pending.entry(port).or_default());
+                        if !data.is_empty() {
+                            window.send(SwoOutput { port, data });
+                        }
+                    }
+                }
+                Err(e) => {
+                    error!("SWO trace read failed: {}", e);
+                    break;
+                }
+            }
+        }
+
+        info!("SWO trace closed.");
+    }
+
+    fn disable(tcl_port: u16, tpiu: &Option<String>) -> Result<String, ErrorMsg> {
+        let mut tcl = TclClient::connect(tcl_port)?;
+        match tpiu {
+            Some(name) => tcl.execute_checked(&format!("{} disable", name)),
+            None => tcl.execute_checked("tpiu config disable"),
+        }
+    }
+}
+
+impl Drop for Swo {
+    fn drop(&mut self) {
+        self.stream.shutdown(Shutdown::Both).ok();
+
+        // OpenOCD may be already stopped, so errors are only logged
+        match Self::disable(self.tcl_port, &self.tpiu) {
+            Ok(_) => info!("SWO stopped."),
+            Err(e) => warn!("SWO was not stopped cleanly: {}", e.message),
+        }
+    }
+}
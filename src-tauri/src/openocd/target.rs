@@ -0,0 +1,208 @@
+use lazy_static::lazy_static;
+use log::{error, info, warn};
+use regex::Regex;
+use std::net::{Shutdown, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::error::ErrorMsg;
+use crate::openocd::{
+    events::{RunState, TargetState},
+    rpc::TclClient,
+    session::SessionWindow,
+};
+
+/// Pause between attempts to connect while OpenOCD examines targets
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Last known states of targets shared with the session
+pub type States = Arc<Mutex<Vec<TargetState>>>;
+
+lazy_static! {
+    static ref TARGET_EVENT: Regex = Regex::new(r"type target_event event (\S+)").unwrap();
+    static ref PC: Regex = Regex::new(r"pc \(/\d+\): (0x[0-9a-fA-F]+)").unwrap();
+}
+
+/// Tracker of run states of targets
+///
+/// `tcl_notifications` are enabled on a dedicated TCL connection. OpenOCD
+/// doesn't tell which target has sent a notification, so states of all
+/// targets are read again on every target event and changed ones are
+/// emitted as `app://openocd/target` event of the session. The connection
+/// is closed on drop.
+///
+/// OpenOCD opens the TCL port before targets are examined and answers only
+/// after that, so the monitor connects in its own thread and retries until
+/// the targets are read.
+pub struct TargetMonitor {
+    /// Notifications connection, set once the monitor is connected
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl TargetMonitor {
+    pub fn start(tcl_port: u16, states: States, window: SessionWindow) -> TargetMonitor {
+        let monitor = TargetMonitor {
+            stream: Arc::new(Mutex::new(None)),
+            stopped: Arc::new(AtomicBool::new(false)),
+        };
+        let slot = monitor.stream.clone();
+        let stopped = monitor.stopped.clone();
+
+        thread::spawn(move || {
+            let mut attempt = 0;
+            let (mut query, mut notifications) = loop {
+                if stopped.load(Ordering::SeqCst) {
+                    return;
+                }
+                match Self::connect(tcl_port, &slot, &stopped) {
+                    Ok((mut query, notifications)) => match read_states(&mut query) {
+                        Ok(current) => {
+                            Self::update(&states, current, &window);
+                            break (query, notifications);
+                        }
+                        Err(e) if attempt == 0 => info!("Targets are not read yet: {}", e.message),
+                        Err(_) => (),
+                    },
+                    Err(e) if attempt == 0 => {
+                        info!("Target monitor is not connected: {}", e.message)
+                    }
+                    Err(_) => (),
+                }
+                attempt += 1;
+                thread::sleep(RETRY_INTERVAL);
+            };
+
+            Self::watch(&mut query, &mut notifications, &states, &window);
+        });
+
+        monitor
+    }
+
+    /// Open the query and the notifications connections
+    ///
+    /// The notifications stream is stored to `slot`, so it can be shut down
+    /// by the drop of the monitor.
+    fn connect(
+        tcl_port: u16,
+        slot: &Mutex<Option<TcpStream>>,
+        stopped: &AtomicBool,
+    ) -> Result<(TclClient, TclClient), ErrorMsg> {
+        let query = TclClient::connect(tcl_port)?;
+        let mut notifications = TclClient::connect(tcl_port)?;
+        notifications.enable_notifications()?;
+
+        let mut slot = slot.lock().unwrap();
+        if stopped.load(Ordering::SeqCst) {
+            return Err("Target monitor is stopped".into());
+        }
+        slot.replace(notifications.try_clone_stream()?);
+
+        Ok((query, notifications))
+    }
+
+    fn watch(
+        query: &mut TclClient,
+        notifications: &mut TclClient,
+        states: &States,
+        window: &SessionWindow,
+    ) {
+        loop {
+            let event = match notifications.read_notification() {
+                Ok(message) => match TARGET_EVENT.captures(&message) {
+                    Some(caps) => caps[1].to_string(),
+                    None => continue,
+                },
+                Err(e) => {
+                    info!("Target notifications closed: {}", e.message);
+                    break;
+                }
+            };
+
+            let current = if event == "reset-start" {
+                // Targets are still reported running until the reset is done
+                states
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .map(|state| TargetState {
+                        target: state.target.clone(),
+                        state: RunState::Reset,
+                        pc: None,
+                    })
+                    .collect()
+            } else {
+                match read_states(query) {
+                    Ok(current) => current,
+                    Err(e) => {
+                        error!("Can't read target states: {}", e.message);
+                        break;
+                    }
+                }
+            };
+
+            Self::update(states, current, window);
+        }
+    }
+
+    /// Store states and emit the changed ones
+    fn update(states: &States, current: Vec<TargetState>, window: &SessionWindow) {
+        let mut states = states.lock().unwrap();
+
+        for state in &current {
+            if !states.contains(state) {
+                info!("Target state: {:?}", state);
+                window.send(state.clone());
+            }
+        }
+        *states = current;
+    }
+}
+
+impl Drop for TargetMonitor {
+    fn drop(&mut self) {
+        let mut stream = self.stream.lock().unwrap();
+        self.stopped.store(true, Ordering::SeqCst);
+
+        if let Some(stream) = stream.take() {
+            if let Err(e) = stream.shutdown(Shutdown::Both) {
+                warn!("Target notifications were not closed: {}", e);
+            }
+        }
+    }
+}
+
+/// Read run states of all targets, with PC of halted ones
+pub fn read_states(tcl: &mut TclClient) -> Result<Vec<TargetState>, ErrorMsg> {
+    let names = tcl.execute_checked("target names")?;
+    let mut states = Vec::new();
+
+    for target in names.split_whitespace() {
+        let state = RunState::parse(&tcl.execute_checked(&format!("{} curstate", target))?);
+
+        // `targets` changes the current target of this connection only
+        let pc = if state == RunState::Halted {
+            tcl.execute_checked(&format!("targets {}; reg pc", target))
+                .ok()
+                .and_then(|output| parse_pc(&output))
+        } else {
+            None
+        };
+
+        states.push(TargetState {
+            target: target.to_string(),
+            state,
+            pc,
+        });
+    }
+
+    Ok(states)
+}
+
+/// Parse the output of `reg pc`, e.g. `pc (/32): 0x08000abc`
+fn parse_pc(output: &str) -> Option<u64> {
+    let caps = PC.captures(output)?;
+    u64::from_str_radix(caps[1].trim_start_matches("0x"), 16).ok()
+}
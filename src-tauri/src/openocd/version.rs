@@ -0,0 +1,373 @@
+use lazy_static::lazy_static;
+use log::{info, warn};
+use regex::Regex;
+use serde::Serialize;
+use std::path::Path;
+use std::thread;
+
+use crate::openocd::proc;
+
+/// Prefix of lines printed by the probe script
+const PROBE_MARK: &str = "jrmd-command";
+
+/// Top-level commands checked with `info commands`
+const PROBED_COMMANDS: &[&str] = &["adapter_khz", "hla_serial", "rtt", "tpiu"];
+
+lazy_static! {
+    static ref VERSION: Regex = Regex::new(
+        r"^(?P<dist>.*?)\s*Open On-Chip Debugger v?(?P<major>\d+)\.(?P<minor>\d+)\.(?P<patch>\d+)(?P<suffix>\S*)(?:\s+\((?P<date>[^)]+)\))?"
+    )
+    .unwrap();
+    static ref PROBED: Regex = Regex::new(&format!(r"^{} (\S+) (\d+)", PROBE_MARK)).unwrap();
+    static ref DETECTED: Capabilities = Capabilities::detect();
+}
+
+/// Version of OpenOCD parsed from `openocd --version`
+///
+/// ```text
+/// Open On-Chip Debugger 0.11.0
+/// Open On-Chip Debugger 0.12.0+dev-01234-g5f7b2a1c6 (2023-05-16-12:00)
+/// xPack OpenOCD x86_64 Open On-Chip Debugger 0.11.0+dev (2021-10-16-21:15)
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    /// Development build between releases
+    pub dev: bool,
+    /// Text following the version, e.g. `+dev-01234-g5f7b2a1c6` or `-rc2`
+    pub suffix: Option<String>,
+    /// Build date in the format of the build, e.g. `2021-10-16-21:15`
+    pub date: Option<String>,
+    /// Name of the distribution, e.g. `xPack`
+    pub distribution: Option<String>,
+}
+
+impl Version {
+    pub fn parse(output: &str) -> Option<Version> {
+        let caps = output
+            .lines()
+            .find_map(|line| VERSION.captures(line.trim()))?;
+
+        let suffix = caps
+            .name("suffix")
+            .map(|suffix| suffix.as_str().to_string())
+            .filter(|suffix| !suffix.is_empty());
+        let distribution = caps
+            .name("dist")
+            .and_then(|dist| dist.as_str().split_whitespace().next())
+            .map(str::to_string);
+
+        Some(Version {
+            major: caps["major"].parse().ok()?,
+            minor: caps["minor"].parse().ok()?,
+            patch: caps["patch"].parse().ok()?,
+            dev: matches!(&suffix, Some(suffix) if suffix.contains("dev")),
+            suffix,
+            date: caps.name("date").map(|date| date.as_str().to_string()),
+            distribution,
+        })
+    }
+}
+
+/// Commands supported by the installed OpenOCD
+///
+/// Detected once by `openocd --version` and a probe run of OpenOCD without
+/// configs. If OpenOCD didn't run, nothing is known and the generated
+/// commands try both the new and the legacy forms.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Capabilities {
+    pub version: Option<Version>,
+    /// Commands were probed, otherwise the flags are unknown
+    pub probed: bool,
+    /// `adapter speed` instead of `adapter_khz`
+    pub adapter_speed: bool,
+    /// `adapter serial` instead of commands of adapter drivers, e.g. `hla_serial`
+    pub adapter_serial: bool,
+    /// Legacy `adapter_khz` is still available
+    pub adapter_khz: bool,
+    /// Legacy `hla_serial` is still available
+    pub hla_serial: bool,
+    pub rtt: bool,
+    /// TPIU objects of OpenOCD 0.12, `tpiu create` and `tpiu names` instead
+    /// of `tpiu config`
+    pub tpiu_objects: bool,
+}
+
+impl Capabilities {
+    /// Return capabilities of the installed OpenOCD, detected on the first call
+    pub fn get() -> &'static Capabilities {
+        &DETECTED
+    }
+
+    /// Detect capabilities in a thread at the app start
+    ///
+    /// Detection runs OpenOCD twice, so the first start of a session
+    /// doesn't wait for it.
+    pub fn detect_in_background() {
+        thread::spawn(|| {
+            Capabilities::get();
+        });
+    }
+
+    fn detect() -> Capabilities {
+        let version = proc::start_exec(Path::new("openocd"), vec!["--version".into()])
+            .and_then(|output| Version::parse(&output));
+
+        let mut capabilities = Capabilities {
+            version,
+            ..Capabilities::default()
+        };
+
+        match proc::probe(probe_commands()) {
+            Some(output) => capabilities.apply_probe(&output),
+            None => warn!("OpenOCD commands were not probed."),
+        }
+
+        info!("OpenOCD capabilities: {:?}", capabilities);
+        capabilities
+    }
+
+    /// Fill flags from the output of the probe script
+    ///
+    /// Subcommands are not visible to `info commands`, so they are found in
+    /// the output of `help adapter` and `help tpiu`. Development builds
+    /// between releases may have any of them, so the version is not used.
+    fn apply_probe(&mut self, output: &str) {
+        let mut found = Vec::new();
+        let mut marks = 0;
+
+        for line in output.lines().map(str::trim) {
+            if let Some(caps) = PROBED.captures(line) {
+                marks += 1;
+                if &caps[2] != "0" {
+                    found.push(caps[1].to_string());
+                }
+            } else if line.starts_with("adapter speed") {
+                found.push("adapter speed".into());
+            } else if line.starts_with("adapter serial") {
+                found.push("adapter serial".into());
+            } else if line.starts_with("tpiu create") {
+                found.push("tpiu create".into());
+            }
+        }
+
+        // Output of another program or OpenOCD has failed before the probe
+        if marks != PROBED_COMMANDS.len() {
+            warn!("Unexpected output of OpenOCD probe: {}", output);
+            return;
+        }
+
+        let has = |name: &str| found.iter().any(|found| found == name);
+        self.probed = true;
+        self.adapter_speed = has("adapter speed");
+        self.adapter_serial = has("adapter serial");
+        self.adapter_khz = has("adapter_khz");
+        self.hla_serial = has("hla_serial");
+        self.rtt = has("rtt");
+        // `tpiu` of older versions is a group with `config` only
+        self.tpiu_objects = has("tpiu create");
+    }
+}
+
+fn probe_commands() -> Vec<String> {
+    let mut commands: Vec<String> = PROBED_COMMANDS
+        .iter()
+        .map(|command| {
+            format!(
+                "echo \"{} {} [llength [info commands {}]]\"",
+                PROBE_MARK, command, command
+            )
+        })
+        .collect();
+
+    commands.push("help adapter".into());
+    commands.push("help tpiu".into());
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Capabilities, Version};
+
+    /// Probe output in the format of OpenOCD 0.12.0, `help` output is cut
+    const PROBE_0_12: &str = "\
+Open On-Chip Debugger 0.12.0
+Licensed under GNU GPL v2
+For bug reports, read
+\thttp://openocd.org/doc/doxygen/bugs.html
+jrmd-command adapter_khz 0
+jrmd-command hla_serial 0
+jrmd-command rtt 1
+jrmd-command tpiu 1
+  adapter
+        adapter command group (command valid any time)
+    adapter assert |deassert [srst|trst [assert|deassert srst|trst]]
+          Controls SRST and TRST lines. (command valid any time)
+    adapter driver driver_name
+          Select a debug adapter driver (configuration command)
+    adapter serial serial_string
+          Set the serial number of the adapter (configuration command)
+    adapter speed [khz]
+          With an argument, change to the specified maximum jtag speed.
+  tpiu
+        tpiu command group (command valid any time)
+    tpiu create name '-dap' dap_name '-ap-num' ap_num '-baseaddr'
+              baseaddr ['-protocol' (sync|uart|manchester)]
+          Creates a new TPIU or SWO object (command valid any time)
+    tpiu init
+          Initialize TPIU objects (command valid any time)
+    tpiu names
+          Lists all registered TPIU and SWO objects by name (command valid
+          any time)
+";
+
+    /// Probe output in the format of OpenOCD 0.11.0
+    const PROBE_0_11: &str = "\
+Open On-Chip Debugger 0.11.0
+Licensed under GNU GPL v2
+For bug reports, read
+\thttp://openocd.org/doc/doxygen/bugs.html
+jrmd-command adapter_khz 1
+jrmd-command hla_serial 1
+jrmd-command rtt 1
+jrmd-command tpiu 1
+  adapter
+        adapter command group (command valid any time)
+    adapter driver driver_name
+          Select a debug adapter driver (configuration command)
+    adapter speed [khz]
+          With an argument, change to the specified maximum jtag speed.
+  tpiu
+        tpiu command group (command valid any time)
+    tpiu config (disable | ((external | internal (<filename> | <:port> |
+              -)) (sync <port width> | ((manchester | uart) <formatter
+              enable>)) <TRACECLKIN freq> [<trace freq>]))
+          Configure TPIU features (command valid any time)
+";
+
+    /// Probe output of a development build between 0.11 and 0.12 which
+    /// has `adapter serial`, but no TPIU objects yet
+    const PROBE_0_11_DEV: &str = "\
+xPack OpenOCD x86_64 Open On-Chip Debugger 0.11.0+dev (2021-10-16-21:15)
+jrmd-command adapter_khz 1
+jrmd-command hla_serial 1
+jrmd-command rtt 1
+jrmd-command tpiu 1
+    adapter serial serial_string
+    adapter speed [khz]
+    tpiu config (disable | ((external | internal (<filename> | <:port> |
+";
+
+    fn probed(output: &str) -> Capabilities {
+        let mut capabilities = Capabilities {
+            version: Version::parse(output),
+            ..Capabilities::default()
+        };
+        capabilities.apply_probe(output);
+        capabilities
+    }
+
+    #[test]
+    fn parse_release() {
+        assert_eq!(
+            Version::parse("Open On-Chip Debugger 0.11.0"),
+            Some(Version {
+                major: 0,
+                minor: 11,
+                patch: 0,
+                dev: false,
+                suffix: None,
+                date: None,
+                distribution: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_dev_build() {
+        assert_eq!(
+            Version::parse("Open On-Chip Debugger 0.12.0+dev-01234-g5f7b2a1c6 (2023-05-16-12:00)"),
+            Some(Version {
+                major: 0,
+                minor: 12,
+                patch: 0,
+                dev: true,
+                suffix: Some("+dev-01234-g5f7b2a1c6".into()),
+                date: Some("2023-05-16-12:00".into()),
+                distribution: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_xpack() {
+        let output = "xPack OpenOCD x86_64 Open On-Chip Debugger 0.11.0+dev (2021-10-16-21:15)
+Licensed under GNU GPL v2
+For bug reports, read
+\thttp://openocd.org/doc/doxygen/bugs.html
+";
+        assert_eq!(
+            Version::parse(output),
+            Some(Version {
+                major: 0,
+                minor: 11,
+                patch: 0,
+                dev: true,
+                suffix: Some("+dev".into()),
+                date: Some("2021-10-16-21:15".into()),
+                distribution: Some("xPack".into()),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_not_openocd() {
+        assert_eq!(Version::parse("bash: openocd: command not found"), None);
+    }
+
+    #[test]
+    fn probe_0_12() {
+        let capabilities = probed(PROBE_0_12);
+
+        assert!(capabilities.probed);
+        assert!(capabilities.adapter_speed);
+        assert!(capabilities.adapter_serial);
+        assert!(!capabilities.adapter_khz);
+        assert!(!capabilities.hla_serial);
+        assert!(capabilities.rtt);
+        assert!(capabilities.tpiu_objects);
+    }
+
+    #[test]
+    fn probe_0_11() {
+        let capabilities = probed(PROBE_0_11);
+
+        assert!(capabilities.probed);
+        assert!(capabilities.adapter_speed);
+        assert!(!capabilities.adapter_serial);
+        assert!(capabilities.adapter_khz);
+        assert!(capabilities.hla_serial);
+        assert!(capabilities.rtt);
+        assert!(!capabilities.tpiu_objects);
+    }
+
+    #[test]
+    fn probe_dev_build_without_tpiu_objects() {
+        let capabilities = probed(PROBE_0_11_DEV);
+
+        assert!(capabilities.version.as_ref().unwrap().dev);
+        assert!(capabilities.adapter_serial);
+        assert!(!capabilities.tpiu_objects);
+    }
+
+    #[test]
+    fn probe_failed() {
+        // OpenOCD has failed before the probe, e.g. a bad interface config
+        let capabilities = probed("Error: unable to open ftdi device with vid 0403, pid 6010\n");
+
+        assert!(!capabilities.probed);
+        assert!(!capabilities.rtt);
+    }
+}
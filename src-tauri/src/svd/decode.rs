@@ -0,0 +1,146 @@
+use serde::Serialize;
+
+use crate::error::ErrorMsg;
+use crate::openocd;
+use crate::openocd::memory::Width;
+use crate::openocd::rpc::TclClient;
+use crate::svd::{Field, Peripheral, Register};
+
+/// Register of a peripheral with its value read from the target
+#[derive(Clone, Serialize)]
+pub struct DecodedRegister {
+    pub name: String,
+    pub description: Option<String>,
+    pub address: u64,
+    pub size: u32,
+    /// `None` if the register was not read, see `error`
+    pub value: Option<u64>,
+    pub error: Option<String>,
+    pub fields: Vec<DecodedField>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct DecodedField {
+    pub name: String,
+    pub description: Option<String>,
+    pub bit_offset: u32,
+    pub bit_width: u32,
+    pub value: u64,
+    /// Name of the matching enumerated value
+    pub enumerated: Option<String>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct DecodedPeripheral {
+    pub name: String,
+    pub description: Option<String>,
+    pub base_address: u64,
+    pub registers: Vec<DecodedRegister>,
+}
+
+/// Read registers of the peripheral and decode their fields
+///
+/// Write-only registers and registers with side effects on read are not
+/// read. A register which failed to read is reported with an error, other
+/// registers are still read.
+pub fn read_peripheral(tcl: &mut TclClient, peripheral: &Peripheral) -> DecodedPeripheral {
+    // Commands of OpenOCD are checked once for all registers
+    let native = openocd::memory::has_read_memory(tcl);
+
+    let registers = peripheral
+        .registers
+        .iter()
+        .map(|register| {
+            let address = peripheral.base_address + register.offset;
+            let value = native
+                .clone()
+                .and_then(|native| read_register(tcl, native, register, address));
+            match value {
+                Ok(value) => decode_register(register, address, value),
+                Err(e) => DecodedRegister {
+                    name: register.name.clone(),
+                    description: register.description.clone(),
+                    address,
+                    size: register.size,
+                    value: None,
+                    error: Some(e.message),
+                    fields: Vec::new(),
+                },
+            }
+        })
+        .collect();
+
+    DecodedPeripheral {
+        name: peripheral.name.clone(),
+        description: peripheral.description.clone(),
+        base_address: peripheral.base_address,
+        registers,
+    }
+}
+
+fn read_register(
+    tcl: &mut TclClient,
+    native: bool,
+    register: &Register,
+    address: u64,
+) -> Result<u64, ErrorMsg> {
+    if !register.is_readable() {
+        return Err("Register is not read: it is write-only or has side effects on read".into());
+    }
+
+    let width = Width::from_bits(register.size)
+        .ok_or_else(|| format!("Register size of {} bits is not supported", register.size))?;
+
+    openocd::memory::read_with(tcl, native, address, width, 1)?
+        .values
+        .first()
+        .copied()
+        .ok_or_else(|| "No value was read".into())
+}
+
+/// Split a register value to the values of its fields
+pub fn decode_register(register: &Register, address: u64, value: u64) -> DecodedRegister {
+    DecodedRegister {
+        name: register.name.clone(),
+        description: register.description.clone(),
+        address,
+        size: register.size,
+        value: Some(value),
+        error: None,
+        fields: register
+            .fields
+            .iter()
+            .map(|field| decode_field(field, value))
+            .collect(),
+    }
+}
+
+fn decode_field(field: &Field, register: u64) -> DecodedField {
+    let mask = if field.bit_width >= 64 {
+        u64::MAX
+    } else {
+        (1 << field.bit_width) - 1
+    };
+    let value = (register >> field.bit_offset) & mask;
+
+    let enumerated = field
+        .values
+        .iter()
+        .find(|enumerated| enumerated.matches(value))
+        .or_else(|| {
+            field
+                .values
+                .iter()
+                .find(|enumerated| enumerated.value.is_none())
+        })
+        .map(|enumerated| enumerated.name.clone());
+
+    DecodedField {
+        name: field.name.clone(),
+        description: field.description.clone(),
+        bit_offset: field.bit_offset,
+        bit_width: field.bit_width,
+        value,
+        enumerated,
+    }
+}
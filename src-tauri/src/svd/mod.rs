@@ -0,0 +1,539 @@
+//! Parser of CMSIS System View Description (SVD) files
+//!
+//! Only the parts needed to read and decode registers are kept: peripherals,
+//! registers, bitfields and their enumerated values. Clusters are flattened
+//! and `dim` arrays are expanded, so every register has its own address.
+
+pub mod decode;
+
+use roxmltree::{Document, Node};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::error::ErrorMsg;
+
+/// Access rights of a register or a field
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Access {
+    ReadOnly,
+    WriteOnly,
+    ReadWrite,
+    WriteOnce,
+    ReadWriteOnce,
+}
+
+impl Access {
+    fn parse(access: &str) -> Option<Access> {
+        match access {
+            "read-only" => Some(Access::ReadOnly),
+            "write-only" => Some(Access::WriteOnly),
+            "read-write" => Some(Access::ReadWrite),
+            "writeOnce" => Some(Access::WriteOnce),
+            "read-writeOnce" => Some(Access::ReadWriteOnce),
+            _ => None,
+        }
+    }
+
+    pub fn is_readable(self) -> bool {
+        !matches!(self, Access::WriteOnly | Access::WriteOnce)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Device {
+    pub name: String,
+    pub description: Option<String>,
+    pub peripherals: Vec<Peripheral>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Peripheral {
+    pub name: String,
+    pub description: Option<String>,
+    pub group: Option<String>,
+    pub base_address: u64,
+    pub registers: Vec<Register>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Register {
+    /// Name of the register, prefixed with names of its clusters, e.g. `CH0.CR`
+    pub name: String,
+    pub description: Option<String>,
+    /// Offset from the base address of the peripheral
+    pub offset: u64,
+    /// Size of the register in bits
+    pub size: u32,
+    pub access: Option<Access>,
+    pub reset_value: Option<u64>,
+    /// Reading of the register has side effects, e.g. clears flags
+    pub read_action: bool,
+    pub fields: Vec<Field>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Field {
+    pub name: String,
+    pub description: Option<String>,
+    pub bit_offset: u32,
+    pub bit_width: u32,
+    pub access: Option<Access>,
+    pub values: Vec<EnumeratedValue>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct EnumeratedValue {
+    pub name: String,
+    pub description: Option<String>,
+    /// `None` for the default value which matches any other value
+    pub value: Option<u64>,
+    /// Bits of `value` to compare, "don't care" bits of `#1x0` are cleared
+    #[serde(skip)]
+    pub mask: u64,
+}
+
+/// Device without registers, returned to the frontend on SVD load
+#[derive(Clone, Serialize)]
+pub struct DeviceSummary {
+    pub name: String,
+    pub description: Option<String>,
+    pub peripherals: Vec<PeripheralSummary>,
+}
+
+#[derive(Clone, Serialize)]
+pub struct PeripheralSummary {
+    pub name: String,
+    pub description: Option<String>,
+    pub group: Option<String>,
+    pub base_address: u64,
+    pub registers: usize,
+}
+
+impl Device {
+    /// Parse content of an SVD file
+    pub fn parse(svd: &str) -> Result<Device, ErrorMsg> {
+        let doc = Document::parse(svd).map_err(|e| format!("Bad SVD file: {}", e))?;
+        let root = doc.root_element();
+
+        if root.tag_name().name() != "device" {
+            return Err("Bad SVD file: <device> element is expected!".into());
+        }
+
+        let defaults = Properties::default().inherit(root)?;
+        let peripherals = match child(root, "peripherals") {
+            Some(peripherals) => elements(peripherals, "peripheral")
+                .map(|node| parse_peripheral(node, &defaults))
+                .collect::<Result<Vec<Parsed>, ErrorMsg>>()?,
+            None => Vec::new(),
+        };
+
+        Ok(Device {
+            name: text(root, "name").unwrap_or_default(),
+            description: text(root, "description"),
+            peripherals: resolve_derived(peripherals)?,
+        })
+    }
+
+    pub fn summary(&self) -> DeviceSummary {
+        DeviceSummary {
+            name: self.name.clone(),
+            description: self.description.clone(),
+            peripherals: self
+                .peripherals
+                .iter()
+                .map(|peripheral| PeripheralSummary {
+                    name: peripheral.name.clone(),
+                    description: peripheral.description.clone(),
+                    group: peripheral.group.clone(),
+                    base_address: peripheral.base_address,
+                    registers: peripheral.registers.len(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn peripheral(&self, name: &str) -> Option<&Peripheral> {
+        self.peripherals
+            .iter()
+            .find(|peripheral| peripheral.name.eq_ignore_ascii_case(name))
+    }
+}
+
+impl Register {
+    /// Register can be read without side effects
+    pub fn is_readable(&self) -> bool {
+        let is_write_only = matches!(self.access, Some(access) if !access.is_readable());
+        !self.read_action && !is_write_only
+    }
+}
+
+impl EnumeratedValue {
+    pub fn matches(&self, value: u64) -> bool {
+        match self.value {
+            Some(expected) => value & self.mask == expected & self.mask,
+            None => false,
+        }
+    }
+}
+
+/// Register properties inherited from the device by peripherals, clusters
+/// and registers
+#[derive(Clone)]
+struct Properties {
+    size: u32,
+    access: Option<Access>,
+    reset_value: Option<u64>,
+}
+
+impl ::std::default::Default for Properties {
+    fn default() -> Self {
+        Properties {
+            size: 32,
+            access: None,
+            reset_value: None,
+        }
+    }
+}
+
+impl Properties {
+    fn inherit(&self, node: Node) -> Result<Properties, ErrorMsg> {
+        Ok(Properties {
+            size: number(node, "size")?.map_or(self.size, |size| size as u32),
+            access: text(node, "access")
+                .and_then(|access| Access::parse(&access))
+                .or(self.access),
+            reset_value: number(node, "resetValue")?.or(self.reset_value),
+        })
+    }
+}
+
+/// Peripheral with the name of a peripheral it is derived from
+struct Parsed {
+    peripheral: Peripheral,
+    derived_from: Option<String>,
+}
+
+fn parse_peripheral(node: Node, defaults: &Properties) -> Result<Parsed, ErrorMsg> {
+    let name = text(node, "name").ok_or("Bad SVD file: peripheral without a name!")?;
+    let properties = defaults.inherit(node)?;
+
+    let registers = match child(node, "registers") {
+        Some(registers) => parse_registers(registers, 0, "", &properties)?,
+        None => Vec::new(),
+    };
+
+    Ok(Parsed {
+        peripheral: Peripheral {
+            description: text(node, "description"),
+            group: text(node, "groupName"),
+            base_address: number(node, "baseAddress")?.unwrap_or_default(),
+            registers,
+            name,
+        },
+        derived_from: node.attribute("derivedFrom").map(str::to_string),
+    })
+}
+
+/// Parse registers and clusters inside `<registers>` or `<cluster>`
+fn parse_registers(
+    node: Node,
+    offset: u64,
+    prefix: &str,
+    properties: &Properties,
+) -> Result<Vec<Register>, ErrorMsg> {
+    let mut registers = Vec::new();
+
+    for item in node.children().filter(Node::is_element) {
+        let kind = item.tag_name().name();
+        if kind != "register" && kind != "cluster" {
+            continue;
+        }
+
+        let name = text(item, "name").ok_or("Bad SVD file: register without a name!")?;
+        let item_offset = offset + number(item, "addressOffset")?.unwrap_or_default();
+        let item_properties = properties.inherit(item)?;
+
+        for (name, item_offset) in expand_dim(item, &name, item_offset)? {
+            let name = format!("{}{}", prefix, name);
+
+            if kind == "cluster" {
+                let prefix = format!("{}.", name);
+                registers.extend(parse_registers(
+                    item,
+                    item_offset,
+                    &prefix,
+                    &item_properties,
+                )?);
+            } else {
+                registers.push(parse_register(item, name, item_offset, &item_properties)?);
+            }
+        }
+    }
+
+    Ok(registers)
+}
+
+fn parse_register(
+    node: Node,
+    name: String,
+    offset: u64,
+    properties: &Properties,
+) -> Result<Register, ErrorMsg> {
+    let fields = match child(node, "fields") {
+        Some(fields) => elements(fields, "field")
+            .map(|field| parse_field(field, properties.access))
+            .collect::<Result<Vec<Field>, ErrorMsg>>()?,
+        None => Vec::new(),
+    };
+
+    Ok(Register {
+        name,
+        description: text(node, "description"),
+        offset,
+        size: properties.size,
+        access: properties.access,
+        reset_value: properties.reset_value,
+        read_action: child(node, "readAction").is_some(),
+        fields,
+    })
+}
+
+fn parse_field(node: Node, access: Option<Access>) -> Result<Field, ErrorMsg> {
+    let name = text(node, "name").ok_or("Bad SVD file: field without a name!")?;
+
+    let (bit_offset, bit_width) = if let Some(offset) = number(node, "bitOffset")? {
+        (offset, number(node, "bitWidth")?.unwrap_or(1))
+    } else if let Some(lsb) = number(node, "lsb")? {
+        let msb = number(node, "msb")?.unwrap_or(lsb);
+        (lsb, msb.saturating_sub(lsb) + 1)
+    } else if let Some(range) = text(node, "bitRange") {
+        parse_bit_range(&range)
+            .ok_or_else(|| format!("Bad SVD file: bad bit range of field {}!", name))?
+    } else {
+        return Err(format!("Bad SVD file: field {} without bit position!", name).into());
+    };
+
+    // Offsets of malformed files may overflow
+    let end = bit_offset.checked_add(bit_width);
+    if bit_width == 0 || !matches!(end, Some(end) if end <= 64) {
+        return Err(format!("Bad SVD file: field {} is out of 64 bits!", name).into());
+    }
+
+    // Values used on write only are not useful to decode a read value
+    let values = elements(node, "enumeratedValues")
+        .filter(|values| !matches!(text(*values, "usage").as_deref(), Some("write")))
+        .flat_map(|values| elements(values, "enumeratedValue"))
+        .map(parse_enumerated_value)
+        .collect::<Result<Vec<EnumeratedValue>, ErrorMsg>>()?;
+
+    Ok(Field {
+        description: text(node, "description"),
+        access: text(node, "access")
+            .and_then(|access| Access::parse(&access))
+            .or(access),
+        name,
+        bit_offset: bit_offset as u32,
+        bit_width: bit_width as u32,
+        values,
+    })
+}
+
+fn parse_enumerated_value(node: Node) -> Result<EnumeratedValue, ErrorMsg> {
+    let name = text(node, "name").unwrap_or_default();
+    let is_default = matches!(text(node, "isDefault").as_deref(), Some("true") | Some("1"));
+
+    let (value, mask) = match text(node, "value") {
+        Some(value) if !is_default => {
+            let (value, mask) = parse_enum_value(&value)
+                .ok_or_else(|| format!("Bad SVD file: bad value of {}!", name))?;
+            (Some(value), mask)
+        }
+        _ => (None, 0),
+    };
+
+    Ok(EnumeratedValue {
+        name,
+        description: text(node, "description"),
+        value,
+        mask,
+    })
+}
+
+/// Copy registers of base peripherals to derived ones
+///
+/// Derived peripheral keeps its own registers if it has any.
+fn resolve_derived(parsed: Vec<Parsed>) -> Result<Vec<Peripheral>, ErrorMsg> {
+    let bases: HashMap<String, Peripheral> = parsed
+        .iter()
+        .filter(|parsed| parsed.derived_from.is_none())
+        .map(|parsed| (parsed.peripheral.name.clone(), parsed.peripheral.clone()))
+        .collect();
+
+    parsed
+        .into_iter()
+        .map(
+            |Parsed {
+                 mut peripheral,
+                 derived_from,
+             }| {
+                if let Some(base) = derived_from {
+                    let base = bases.get(&base).ok_or_else(|| {
+                        format!(
+                            "Bad SVD file: peripheral {} is derived from unknown {}!",
+                            peripheral.name, base
+                        )
+                    })?;
+
+                    if peripheral.registers.is_empty() {
+                        peripheral.registers = base.registers.clone();
+                    }
+                    if peripheral.description.is_none() {
+                        peripheral.description = base.description.clone();
+                    }
+                    if peripheral.group.is_none() {
+                        peripheral.group = base.group.clone();
+                    }
+                }
+                Ok(peripheral)
+            },
+        )
+        .collect()
+}
+
+/// Return names and offsets of elements of a `dim` array
+///
+/// `%s` in the name is replaced with an index from `dimIndex` or with the
+/// element number, so `INT[%s]` becomes `INT[0]`. An element without `dim` is returned as is.
+fn expand_dim(node: Node, name: &str, offset: u64) -> Result<Vec<(String, u64)>, ErrorMsg> {
+    let dim = match number(node, "dim")? {
+        Some(dim) => dim,
+        None => return Ok(vec![(name.to_string(), offset)]),
+    };
+    let increment = number(node, "dimIncrement")?.unwrap_or_default();
+
+    let indexes: Vec<String> = match text(node, "dimIndex") {
+        Some(index) => parse_dim_index(&index)
+            .ok_or_else(|| format!("Bad SVD file: bad dimIndex of {}!", name))?,
+        None => (0..dim).map(|i| i.to_string()).collect(),
+    };
+
+    if indexes.len() as u64 != dim {
+        return Err(format!("Bad SVD file: dimIndex of {} doesn't match dim!", name).into());
+    }
+
+    Ok(indexes
+        .iter()
+        .enumerate()
+        .map(|(i, index)| (name.replace("%s", index), offset + i as u64 * increment))
+        .collect())
+}
+
+/// Parse `dimIndex` in `0-3` or `A,B,C` form
+fn parse_dim_index(index: &str) -> Option<Vec<String>> {
+    if let Some((first, last)) = index.split_once('-') {
+        let first: u64 = first.trim().parse().ok()?;
+        let last: u64 = last.trim().parse().ok()?;
+        return Some((first..=last).map(|i| i.to_string()).collect());
+    }
+
+    Some(index.split(',').map(|i| i.trim().to_string()).collect())
+}
+
+/// Parse bit range in `[msb:lsb]` form to offset and width
+fn parse_bit_range(range: &str) -> Option<(u64, u64)> {
+    let (msb, lsb) = range
+        .trim()
+        .strip_prefix('[')?
+        .strip_suffix(']')?
+        .split_once(':')?;
+    let msb: u64 = msb.trim().parse().ok()?;
+    let lsb: u64 = lsb.trim().parse().ok()?;
+
+    if msb < lsb {
+        None
+    } else {
+        Some((lsb, msb - lsb + 1))
+    }
+}
+
+/// Parse value of an enumerated value to the value and the mask of its bits
+///
+/// Binary values may contain "don't care" bits, e.g. `#1x0`.
+fn parse_enum_value(value: &str) -> Option<(u64, u64)> {
+    match value.trim().strip_prefix('#') {
+        Some(bits) if !bits.is_empty() && bits.len() <= 64 => {
+            bits.chars().try_fold((0u64, 0u64), |(value, mask), bit| {
+                let (bit, care) = match bit {
+                    '0' => (0, 1),
+                    '1' => (1, 1),
+                    'x' | 'X' => (0, 0),
+                    _ => return None,
+                };
+                Some(((value << 1) | bit, (mask << 1) | care))
+            })
+        }
+        Some(_) => None,
+        None => parse_number(value).map(|value| (value, u64::MAX)),
+    }
+}
+
+/// Parse `scaledNonNegativeInteger` of SVD: decimal, `0x` hexadecimal or
+/// `#` binary number with an optional `k`, `M`, `G` or `T` multiplier
+fn parse_number(number: &str) -> Option<u64> {
+    let number = number.trim();
+
+    let (number, scale) = match number.chars().last()? {
+        'k' | 'K' => (&number[..number.len() - 1], 1 << 10),
+        'm' | 'M' => (&number[..number.len() - 1], 1 << 20),
+        'g' | 'G' => (&number[..number.len() - 1], 1 << 30),
+        't' | 'T' => (&number[..number.len() - 1], 1 << 40),
+        _ => (number, 1),
+    };
+
+    let value = if let Some(hex) = number
+        .strip_prefix("0x")
+        .or_else(|| number.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = number.strip_prefix('#') {
+        u64::from_str_radix(bin, 2).ok()?
+    } else {
+        number.parse().ok()?
+    };
+
+    value.checked_mul(scale)
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children()
+        .find(|child| child.is_element() && child.tag_name().name() == name)
+}
+
+fn elements<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// Return text of a child element with whitespace collapsed
+fn text(node: Node, name: &str) -> Option<String> {
+    child(node, name)
+        .and_then(|child| child.text())
+        .map(|text| text.split_whitespace().collect::<Vec<&str>>().join(" "))
+        .filter(|text| !text.is_empty())
+}
+
+fn number(node: Node, name: &str) -> Result<Option<u64>, ErrorMsg> {
+    match text(node, name) {
+        Some(value) => parse_number(&value)
+            .map(Some)
+            .ok_or_else(|| format!("Bad SVD file: bad number \"{}\" in <{}>!", value, name).into()),
+        None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,209 @@
+use super::decode::decode_register;
+use super::{Access, Device, Register};
+
+const ARM_EXAMPLE: &str = include_str!("samples/ARM_Example.svd");
+const CLUSTERS: &str = include_str!("samples/Clusters.svd");
+
+fn register<'a>(device: &'a Device, peripheral: &str, name: &str) -> &'a Register {
+    device
+        .peripheral(peripheral)
+        .unwrap()
+        .registers
+        .iter()
+        .find(|register| register.name == name)
+        .unwrap()
+}
+
+#[test]
+fn parses_peripherals_and_registers() {
+    let device = Device::parse(ARM_EXAMPLE).unwrap();
+
+    assert_eq!(device.name, "ARM_Example");
+    let names: Vec<&str> = device.peripherals.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(names, ["TIMER0", "TIMER1", "TIMER2"]);
+
+    let timer0 = device.peripheral("timer0").unwrap();
+    assert_eq!(timer0.base_address, 0x4001_0000);
+    assert_eq!(timer0.group.as_deref(), Some("TIMER"));
+
+    let names: Vec<&str> = timer0.registers.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["CR", "SR", "INT[0]", "INT[1]", "INT[2]", "INT[3]", "COUNT", "MATCH", "RELOAD"]
+    );
+
+    let sr = register(&device, "TIMER0", "SR");
+    assert_eq!(sr.offset, 0x04);
+    assert_eq!(sr.size, 16);
+    assert_eq!(sr.access, Some(Access::ReadWrite));
+
+    assert_eq!(register(&device, "TIMER0", "INT[3]").offset, 0x1c);
+}
+
+#[test]
+fn parses_fields_with_enumerated_values() {
+    let device = Device::parse(ARM_EXAMPLE).unwrap();
+    let cr = register(&device, "TIMER0", "CR");
+
+    let mode = cr.fields.iter().find(|field| field.name == "MODE").unwrap();
+    assert_eq!((mode.bit_offset, mode.bit_width), (4, 3));
+    assert_eq!(mode.values.len(), 5);
+    assert_eq!(mode.values[2].name, "Single_MATCH");
+
+    let rst = cr.fields.iter().find(|field| field.name == "RST").unwrap();
+    assert_eq!(rst.access, Some(Access::WriteOnly));
+}
+
+#[test]
+fn copies_registers_to_derived_peripherals() {
+    let device = Device::parse(ARM_EXAMPLE).unwrap();
+
+    let timer2 = device.peripheral("TIMER2").unwrap();
+    assert_eq!(timer2.base_address, 0x4001_0200);
+    assert_eq!(timer2.registers.len(), 9);
+    assert_eq!(
+        timer2.description.as_deref(),
+        Some("32 Timer / Counter, counting up or down from different sources")
+    );
+}
+
+#[test]
+fn flattens_clusters_and_expands_dim() {
+    let device = Device::parse(CLUSTERS).unwrap();
+    let dma = device.peripheral("DMA").unwrap();
+
+    let registers: Vec<(&str, u64)> = dma
+        .registers
+        .iter()
+        .map(|r| (r.name.as_str(), r.offset))
+        .collect();
+    assert_eq!(
+        registers,
+        [
+            ("ISR", 0x00),
+            ("IFCR", 0x04),
+            ("CH0.CCR", 0x08),
+            ("CH0.CPAR", 0x10),
+            ("CH0.CMAR", 0x14),
+            ("CH1.CCR", 0x1c),
+            ("CH1.CPAR", 0x24),
+            ("CH1.CMAR", 0x28),
+        ]
+    );
+
+    assert_eq!(device.peripheral("DMA2").unwrap().registers.len(), 8);
+}
+
+#[test]
+fn inherits_register_properties() {
+    let device = Device::parse(CLUSTERS).unwrap();
+
+    assert_eq!(register(&device, "DMA", "CH1.CCR").size, 32);
+    assert_eq!(register(&device, "DMA", "CH1.CCR").reset_value, Some(0x10));
+    assert_eq!(register(&device, "DMA", "CH1.CPAR").reset_value, Some(0));
+    assert_eq!(register(&device, "GPIO", "ODR").size, 16);
+    assert_eq!(register(&device, "GPIO", "LCKR").size, 32);
+}
+
+#[test]
+fn skips_registers_unsafe_to_read() {
+    let device = Device::parse(CLUSTERS).unwrap();
+
+    assert!(!register(&device, "DMA", "ISR").is_readable());
+    assert!(!register(&device, "DMA", "IFCR").is_readable());
+    assert!(register(&device, "DMA", "CH0.CCR").is_readable());
+}
+
+#[test]
+fn parses_alternative_bit_positions() {
+    let device = Device::parse(CLUSTERS).unwrap();
+    let ccr = register(&device, "DMA", "CH0.CCR");
+
+    let positions: Vec<(&str, u32, u32)> = ccr
+        .fields
+        .iter()
+        .map(|f| (f.name.as_str(), f.bit_offset, f.bit_width))
+        .collect();
+    assert_eq!(positions, [("EN", 0, 1), ("PL", 12, 2), ("MSIZE", 10, 2)]);
+
+    let msize = ccr.fields.iter().find(|f| f.name == "MSIZE").unwrap();
+    assert!(msize.values.is_empty());
+}
+
+#[test]
+fn decodes_register_value() {
+    let device = Device::parse(ARM_EXAMPLE).unwrap();
+    let cr = register(&device, "TIMER1", "CR");
+
+    let decoded = decode_register(cr, 0x4001_0100, 0x8000_0225);
+    let fields: Vec<(&str, u64, Option<&str>)> = decoded
+        .fields
+        .iter()
+        .map(|f| (f.name.as_str(), f.value, f.enumerated.as_deref()))
+        .collect();
+
+    assert_eq!(
+        fields,
+        [
+            ("EN", 1, Some("Enable")),
+            ("RST", 0, Some("Reserved")),
+            ("CNT", 1, Some("Count_DOWN")),
+            ("MODE", 2, Some("Single_MATCH")),
+            ("PSC", 0, Some("Disabled")),
+            ("CNTSRC", 2, Some("CAP_SRC_div4")),
+            ("IDR", 2, Some("SET")),
+        ]
+    );
+}
+
+#[test]
+fn decodes_dont_care_and_default_values() {
+    let device = Device::parse(CLUSTERS).unwrap();
+    let ccr = register(&device, "DMA", "CH0.CCR");
+
+    let pl = |value: u64| {
+        decode_register(ccr, 0, value)
+            .fields
+            .into_iter()
+            .find(|f| f.name == "PL")
+            .and_then(|f| f.enumerated)
+            .unwrap()
+    };
+
+    assert_eq!(pl(0x0000), "Low");
+    assert_eq!(pl(0x1000), "Medium");
+    assert_eq!(pl(0x2000), "High");
+    assert_eq!(pl(0x3000), "High");
+}
+
+#[test]
+fn reports_bad_files() {
+    assert!(Device::parse("<device><name>X</device>").is_err());
+    assert!(Device::parse("<peripherals/>").is_err());
+    assert!(Device::parse(
+        "<device><peripherals><peripheral derivedFrom=\"NONE\"><name>A</name></peripheral></peripherals></device>"
+    )
+    .is_err());
+}
+
+#[test]
+fn reports_fields_out_of_register() {
+    let svd = |offset: &str, width: &str| {
+        format!(
+            "<device><name>X</name><peripherals><peripheral>\
+             <name>P</name><baseAddress>0x40000000</baseAddress><registers>\
+             <register><name>R</name><addressOffset>0</addressOffset><size>32</size>\
+             <fields><field><name>F</name><bitOffset>{}</bitOffset><bitWidth>{}</bitWidth></field></fields>\
+             </register></registers></peripheral></peripherals></device>",
+            offset, width
+        )
+    };
+
+    assert!(Device::parse(&svd("31", "1")).is_ok());
+    for (offset, width) in [("63", "2"), ("0", "0"), ("0xFFFFFFFFFFFFFFFF", "2")] {
+        assert_eq!(
+            Device::parse(&svd(offset, width)).unwrap_err().message,
+            "Bad SVD file: field F is out of 64 bits!"
+        );
+    }
+}
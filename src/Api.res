@@ -1,4 +1,6 @@
-type configs_t = {configs: array<Openocd.config_file_t>}
+/// Options of OpenOCD launch besides configs, `None` fields are omitted
+type launch_options_t = {adapter_serial: option<string>}
+type configs_t = {configs: array<Openocd.config_file_t>, options: launch_options_t}
 type settings_t = {gitpod: AppTypes.gitpod_settings_t, openocd: Openocd.config_t}
 type dump_state_t = {dumped: settings_t}
 
@@ -10,7 +12,18 @@ type config_lists_t = {
 
 let invoke_get_config_lists = (): Promise.t<config_lists_t> => Tauri.invoke("get_config_lists")
 
-let invoke_start = (cfgs: configs_t): Promise.t<string> => Tauri.invoke1("start", cfgs)
+type ports_t = {
+  gdb: int,
+  telnet: int,
+  tcl: int,
+}
+
+type started_t = {
+  message: string,
+  ports: ports_t,
+}
+
+let invoke_start = (cfgs: configs_t): Promise.t<started_t> => Tauri.invoke1("start", cfgs)
 
 let invoke_kill = (): Promise.t<string> => Tauri.invoke("kill")
 
@@ -69,6 +82,9 @@ module Notification = {
 }
 
 /// Describe content of message of "app://openocd/event" tauri event
+///
+/// Stop event also carries a report of the finished process which is not
+/// decoded here.
 module OpenocdEvent = {
   module Kind = {
     @deriving(jsConverter)
@@ -79,7 +95,11 @@ module OpenocdEvent = {
 
   type t = Kind.t
 
-  let codec = Utils.Json.Jzon.int_enum(Kind.tToJs, Kind.tFromJs)
+  let codec = Jzon.object1(
+    kind => kind,
+    kind => kind->Ok,
+    Jzon.field("kind", Utils.Json.Jzon.int_enum(Kind.tToJs, Kind.tFromJs)),
+  )
 }
 
 /// Describe content of message of "app://openocd/output" tauri event
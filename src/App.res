@@ -77,6 +77,7 @@ let make = (~saveSettings) => {
 
       invoke_start({
         configs: configs->unwrap_opt_array,
+        options: {adapter_serial: settings.openocd.adapter_serial},
       })
       ->then(ret => {
         %log.info(
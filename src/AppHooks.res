@@ -113,6 +113,7 @@ let useSettings = (): (option<Settings.t>, Settings.t => unit) => {
       board: settings.openocd.board->option_to_empty_cfg,
       interface: settings.openocd.interface->option_to_empty_cfg,
       target: settings.openocd.target->option_to_empty_cfg,
+      adapter_serial: settings.openocd.adapter_serial,
     }
 
     invoke_dump_state({dumped: {gitpod: settings.gitpod, openocd: openocd_config}})
@@ -150,6 +151,7 @@ let useSettings = (): (option<Settings.t>, Settings.t => unit) => {
           board: settings.openocd.board->as_option,
           interface: settings.openocd.interface->as_option,
           target: settings.openocd.target->as_option,
+          adapter_serial: settings.openocd.adapter_serial,
         },
         gitpod: settings.gitpod,
       }))
@@ -12,6 +12,7 @@ module Settings = {
         board: None,
         interface: None,
         target: None,
+        adapter_serial: None,
       },
       gitpod: {instance_id: "", hostname: ""},
     }
@@ -7,10 +7,12 @@ type config_set_t = {
   board: option<config_file_t>,
   interface: option<config_file_t>,
   target: option<config_file_t>,
+  adapter_serial: option<string>,
 }
 
 type config_t = {
   board: config_file_t,
   interface: config_file_t,
   target: config_file_t,
+  adapter_serial: option<string>,
 }